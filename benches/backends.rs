@@ -0,0 +1,54 @@
+//! Hashing throughput of each compiled backend.
+//!
+//! Run with `cargo bench --bench backends --all-features`, or with the features of the
+//! backends to compare. `Backend::Reference` multiplies every byte with `multiply_gf` in
+//! `mix_columns`, so it is the baseline the other backends are measured against.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use kupyna::{kupyna_hash_with, Backend};
+
+/// The message hashed in each run.
+const MESSAGE_LEN: usize = 256 * 1024;
+
+/// How long each backend is timed for, at least.
+const MEASURE: Duration = Duration::from_secs(2);
+
+/// Hashes the message repeatedly for at least `MEASURE`.
+///
+/// # Returns
+///
+/// * The throughput in MiB/s.
+fn throughput(backend: Backend, message: &[u8], n: usize) -> f64 {
+    // One run first, so the tables are in cache and the CPU is out of its idle state
+    black_box(kupyna_hash_with(backend, black_box(message), n));
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < MEASURE {
+        black_box(kupyna_hash_with(backend, black_box(message), n));
+        runs += 1;
+    }
+    (runs * message.len()) as f64 / start.elapsed().as_secs_f64() / (1024.0 * 1024.0)
+}
+
+fn main() {
+    let message: Vec<u8> = (0..MESSAGE_LEN).map(|i| (i * 31 + 7) as u8).collect();
+    for n in [256, 512] {
+        let reference = throughput(Backend::Reference, &message, n);
+        println!("Kupyna-{n}");
+        for &backend in Backend::ALL {
+            let speed = if backend == Backend::Reference {
+                reference
+            } else {
+                throughput(backend, &message, n)
+            };
+            println!(
+                "  {:<10} {:>8.2} MiB/s  {:>6.2}x reference",
+                backend.name(),
+                speed,
+                speed / reference
+            );
+        }
+    }
+}
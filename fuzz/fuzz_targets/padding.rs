@@ -0,0 +1,25 @@
+//! Checks that `pad_message` output is block-aligned, starts with the message and its `0x80`
+//! marker, and ends with the message length in bits as a 96-bit little-endian integer.
+
+#![no_main]
+
+use kupyna::fuzzing::pad_message;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|message: &[u8]| {
+    for l in [512, 1024] {
+        let padded = pad_message(message, l);
+        assert_eq!(padded.len() % (l / 8), 0);
+        assert!(padded.len() >= message.len() + 13);
+        assert!(padded.len() < message.len() + 13 + l / 8);
+
+        assert_eq!(&padded[..message.len()], message);
+        assert_eq!(padded[message.len()], 0x80);
+        let zeros = &padded[message.len() + 1..padded.len() - 12];
+        assert!(zeros.iter().all(|&b| b == 0));
+
+        let mut length = [0u8; 16];
+        length[..12].copy_from_slice(&padded[padded.len() - 12..]);
+        assert_eq!(u128::from_le_bytes(length), message.len() as u128 * 8);
+    }
+});
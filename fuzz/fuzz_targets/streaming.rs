@@ -0,0 +1,41 @@
+//! Hashes the input in one shot and through `KupynaHasher` fed by a split schedule, and checks
+//! that the hash codes agree.
+//!
+//! Input layout: one byte giving the schedule length `k` (mod 16), then `k` chunk sizes of one
+//! byte each, then the message. The chunk sizes repeat until the message is used up, and a size
+//! of zero counts as one, so that every chunk makes progress.
+
+#![no_main]
+
+use kupyna::{kupyna_hash, KupynaHasher};
+use libfuzzer_sys::fuzz_target;
+
+/// The standard lengths, and lengths either side of the switch to the 1024-bit state.
+const LENGTHS: [usize; 8] = [8, 160, 224, 248, 256, 264, 384, 512];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&k, rest)) = data.split_first() else {
+        return;
+    };
+    let k = (k % 16) as usize;
+    if rest.len() < k {
+        return;
+    }
+    let (schedule, message) = rest.split_at(k);
+
+    for n in LENGTHS {
+        let mut hasher = KupynaHasher::new(n);
+        let mut remaining = message;
+        for &size in schedule.iter().cycle() {
+            if remaining.is_empty() {
+                break;
+            }
+            let size = (size as usize).max(1);
+            let (chunk, tail) = remaining.split_at(size.min(remaining.len()));
+            hasher.update(chunk);
+            remaining = tail;
+        }
+        hasher.update(remaining);
+        assert_eq!(hasher.finalize(), kupyna_hash(message, n), "n = {}", n);
+    }
+});
@@ -0,0 +1,204 @@
+//! Selection of the implementation behind the T⊕l and T+l permutations.
+//!
+//! The spec-literal reference is always compiled. The optimised backends are chosen with cargo
+//! features, and any compiled backend can be picked at runtime. Every backend computes the same
+//! permutations and so the same digests.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "bitsliced")]
+use crate::bitsliced;
+#[cfg(feature = "compact")]
+use crate::compact;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+use crate::simd;
+#[cfg(feature = "table")]
+use crate::t_table;
+use crate::t_xor_plus;
+
+/// An implementation of the Kupyna permutations.
+///
+/// Which variants exist depends on the enabled features, so the enum is non-exhaustive: a
+/// `match` written against one set of features still compiles when another feature adds a
+/// variant. Iterate over `Backend::ALL` to visit every compiled backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backend {
+    /// The standard's steps one by one: `add_constant_*`, `s_box_layer`, `rotate_rows` and
+    /// `mix_columns`. Slow, but always available.
+    Reference,
+    /// Eight precomputed tables that fold the S-boxes, row rotation and MDS multiplication into
+    /// one lookup per byte. The fastest portable backend.
+    #[cfg(feature = "table")]
+    Table,
+    /// The S-box and MDS tables only, for targets short on flash and RAM.
+    #[cfg(feature = "compact")]
+    Compact,
+    /// Constant-time Boolean circuits over a bitsliced state.
+    #[cfg(feature = "bitsliced")]
+    Bitsliced,
+    /// Constant-time AVX2 or SSSE3 code, detected at runtime, falling back to `Bitsliced`.
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    Simd,
+}
+
+impl Backend {
+    /// Every backend compiled into this build.
+    pub const ALL: &'static [Backend] = &[
+        Backend::Reference,
+        #[cfg(feature = "table")]
+        Backend::Table,
+        #[cfg(feature = "compact")]
+        Backend::Compact,
+        #[cfg(feature = "bitsliced")]
+        Backend::Bitsliced,
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        Backend::Simd,
+    ];
+
+    /// The backend's name, as accepted by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Reference => "reference",
+            #[cfg(feature = "table")]
+            Backend::Table => "table",
+            #[cfg(feature = "compact")]
+            Backend::Compact => "compact",
+            #[cfg(feature = "bitsliced")]
+            Backend::Bitsliced => "bitsliced",
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Backend::Simd => "simd",
+        }
+    }
+
+    /// Whether the backend avoids secret-dependent memory accesses and branches.
+    pub fn is_constant_time(self) -> bool {
+        match self {
+            Backend::Reference => false,
+            #[cfg(feature = "table")]
+            Backend::Table => false,
+            #[cfg(feature = "compact")]
+            Backend::Compact => false,
+            #[cfg(feature = "bitsliced")]
+            Backend::Bitsliced => true,
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Backend::Simd => true,
+        }
+    }
+
+    /// The T⊕l transformation.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+    /// * `rounds` - The number of rounds to perform.
+    ///
+    /// # Returns
+    ///
+    /// * A `Vec<u8>` containing the transformed block.
+    pub fn t_xor_l(self, block: &[u8], rounds: usize) -> Vec<u8> {
+        match self {
+            Backend::Reference => t_xor_plus::t_xor_l(block, rounds),
+            #[cfg(feature = "table")]
+            Backend::Table => t_table::t_xor_l(block, rounds),
+            #[cfg(feature = "compact")]
+            Backend::Compact => compact::t_xor_l(block, rounds),
+            #[cfg(feature = "bitsliced")]
+            Backend::Bitsliced => bitsliced::t_xor_l(block, rounds),
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Backend::Simd => simd::t_xor_l(block, rounds),
+        }
+    }
+
+    /// The T+l transformation.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+    /// * `rounds` - The number of rounds to perform.
+    ///
+    /// # Returns
+    ///
+    /// * A `Vec<u8>` containing the transformed block.
+    pub fn t_plus_l(self, block: &[u8], rounds: usize) -> Vec<u8> {
+        match self {
+            Backend::Reference => t_xor_plus::t_plus_l(block, rounds),
+            #[cfg(feature = "table")]
+            Backend::Table => t_table::t_plus_l(block, rounds),
+            #[cfg(feature = "compact")]
+            Backend::Compact => compact::t_plus_l(block, rounds),
+            #[cfg(feature = "bitsliced")]
+            Backend::Bitsliced => bitsliced::t_plus_l(block, rounds),
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Backend::Simd => simd::t_plus_l(block, rounds),
+        }
+    }
+}
+
+impl Default for Backend {
+    /// The fastest compiled backend, restricted to constant-time ones when the `constant-time`
+    /// feature is enabled.
+    fn default() -> Self {
+        // Ordered by the throughput `benches/backends.rs` measures on x86-64. The SIMD paths come
+        // second: even with AVX2 they run at a quarter of the speed of the tables or less.
+        const FASTEST_FIRST: &[Backend] = &[
+            #[cfg(feature = "table")]
+            Backend::Table,
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Backend::Simd,
+            #[cfg(feature = "compact")]
+            Backend::Compact,
+            Backend::Reference,
+            #[cfg(feature = "bitsliced")]
+            Backend::Bitsliced,
+        ];
+        FASTEST_FIRST
+            .iter()
+            .copied()
+            .filter(|&backend| !runs_bitsliced(backend))
+            .find(|backend| !cfg!(feature = "constant-time") || backend.is_constant_time())
+            .unwrap_or(Backend::Reference)
+    }
+}
+
+/// Whether the backend falls back to the portable bitsliced code, the slowest there is, on the
+/// running CPU.
+fn runs_bitsliced(backend: Backend) -> bool {
+    match backend {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        Backend::Simd => simd::Level::detect() == simd::Level::Portable,
+        _ => false,
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Returned when a backend name is unknown or was not compiled into this build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownBackend(pub String);
+
+impl fmt::Display for UnknownBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown or unavailable backend: {}", self.0)
+    }
+}
+
+impl Error for UnknownBackend {}
+
+impl FromStr for Backend {
+    type Err = UnknownBackend;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Backend::ALL
+            .iter()
+            .copied()
+            .find(|backend| backend.name() == s)
+            .ok_or_else(|| UnknownBackend(s.to_string()))
+    }
+}
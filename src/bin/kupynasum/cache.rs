@@ -0,0 +1,295 @@
+//! A cache of file digests, so files that have not changed since the last run are not read.
+//!
+//! Each entry records a file's absolute path, the digest length, and the file's size,
+//! modification time, device and inode when it was hashed. An entry is used only while all of
+//! them still match, so writing, truncating, or replacing the file (even with one of the same
+//! size and time) makes it stale. Stale entries are replaced when the file is hashed again,
+//! and entries for files that have changed or gone are dropped whenever the cache is saved.
+//!
+//! A file modified within `RACY` of being hashed is not cached: a later write in the same tick
+//! of the file system's clock could leave its metadata unchanged. Neither is a file whose
+//! metadata changed while it was read.
+//!
+//! The cache only ever skips reading. Verifying with `--check` or `manifest --verify`, and
+//! linking with `dupes --link`, always read every file, since a file can change without its
+//! metadata showing it.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Write};
+use std::path::{self, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::check::unescape;
+use crate::encoding::{parse_hex, to_hex};
+use crate::sum::{describe, escape_name};
+
+/// The environment variable naming the cache file to use when no `--cache` is given.
+pub(crate) const CACHE_VARIABLE: &str = "KUPYNASUM_CACHE";
+
+/// The first line of a cache file. A file starting any other way is ignored.
+const HEADER: &str = "kupynasum cache 1";
+
+/// How long after its last modification a file must be hashed for its digest to be cached.
+pub(crate) const RACY: Duration = Duration::from_secs(2);
+
+/// What must not change for a cached digest to stay valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Stamp {
+    size: u64,
+    /// The modification time, in seconds and nanoseconds since the Unix epoch.
+    modified: (u64, u32),
+    device: u64,
+    inode: u64,
+}
+
+impl Stamp {
+    /// The stamp of a file, or `None` if it has no usable modification time.
+    pub(crate) fn of(metadata: &Metadata) -> Option<Stamp> {
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let (device, inode) = file_id(metadata);
+        Some(Stamp {
+            size: metadata.len(),
+            modified: (modified.as_secs(), modified.subsec_nanos()),
+            device,
+            inode,
+        })
+    }
+
+    /// Whether the file was modified too recently for its digest to be trusted later.
+    fn is_racy(&self, now: SystemTime) -> bool {
+        let modified = UNIX_EPOCH + Duration::new(self.modified.0, self.modified.1);
+        now.duration_since(modified).map_or(true, |age| age < RACY)
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+/// Cached digests by absolute path and length in bits, loaded from and saved to one file.
+///
+/// It can be shared between worker threads.
+#[derive(Debug)]
+pub(crate) struct Cache {
+    file: PathBuf,
+    entries: Mutex<Entries>,
+}
+
+/// A file's absolute path and digest length in bits.
+type Key = (PathBuf, usize);
+
+#[derive(Debug, Default)]
+struct Entries {
+    digests: HashMap<Key, (Stamp, Vec<u8>)>,
+    /// Whether anything was added since loading.
+    changed: bool,
+}
+
+impl Cache {
+    /// Loads a cache file. A missing or unrecognised file gives an empty cache, and lines that
+    /// cannot be read are skipped.
+    pub(crate) fn load(file: &Path) -> Cache {
+        let mut digests = HashMap::new();
+        let contents = fs::read_to_string(file).unwrap_or_default();
+        let mut lines = contents.lines();
+        if lines.next() == Some(HEADER) {
+            digests.extend(lines.filter_map(parse_entry));
+        }
+        Cache {
+            file: file.to_path_buf(),
+            entries: Mutex::new(Entries {
+                digests,
+                changed: false,
+            }),
+        }
+    }
+
+    /// Returns the cached digest of a file, or hashes it and caches the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file.
+    /// * `n` - The length of the digest in bits.
+    /// * `hash` - Hashes the file when the cache cannot answer.
+    ///
+    /// # Returns
+    ///
+    /// * The digest, or the error from reading the file.
+    pub(crate) fn digest(
+        &self,
+        path: &Path,
+        n: usize,
+        hash: impl FnOnce(&Path) -> io::Result<Vec<u8>>,
+    ) -> io::Result<Vec<u8>> {
+        let before = Stamp::of(&fs::metadata(path)?);
+        // The cache file holds paths as text, so other names are always hashed
+        let key = match (before, path::absolute(path)) {
+            (Some(_), Ok(absolute)) if absolute.to_str().is_some() => (absolute, n),
+            _ => return hash(path),
+        };
+        if let Some((stamp, digest)) = self.entries.lock().unwrap().digests.get(&key) {
+            if Some(*stamp) == before {
+                return Ok(digest.clone());
+            }
+        }
+
+        let digest = hash(path)?;
+        let after = fs::metadata(path).ok().and_then(|m| Stamp::of(&m));
+        match before {
+            Some(stamp) if after == before && !stamp.is_racy(SystemTime::now()) => {
+                let mut entries = self.entries.lock().unwrap();
+                entries.digests.insert(key, (stamp, digest.clone()));
+                entries.changed = true;
+            }
+            _ => {}
+        }
+        Ok(digest)
+    }
+
+    /// Writes the cache back to its file if anything was added, leaving out entries for files
+    /// that have since changed or gone.
+    ///
+    /// The file is written under a temporary name and renamed over the old one, so a run that
+    /// stops part-way, or a concurrent one, never leaves a truncated cache.
+    pub(crate) fn save(&self) -> io::Result<()> {
+        let entries = self.entries.lock().unwrap();
+        if !entries.changed {
+            return Ok(());
+        }
+        let mut lines: Vec<String> = entries
+            .digests
+            .iter()
+            .filter(|((path, _), (stamp, _))| {
+                let current = fs::metadata(path).ok().and_then(|m| Stamp::of(&m));
+                current == Some(*stamp)
+            })
+            .map(|((path, n), (stamp, digest))| format_entry(path, *n, stamp, digest))
+            .collect();
+        lines.sort_unstable();
+
+        if let Some(dir) = self.file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temporary = self.file.clone().into_os_string();
+        temporary.push(format!(".{}.tmp", std::process::id()));
+        let write = File::create(&temporary).and_then(|mut file| {
+            writeln!(file, "{}", HEADER)?;
+            for line in &lines {
+                writeln!(file, "{}", line)?;
+            }
+            file.sync_all()
+        });
+        write
+            .and_then(|_| fs::rename(&temporary, &self.file))
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&temporary);
+            })
+    }
+}
+
+/// Formats a cache entry as `<bits> <size> <seconds>.<nanoseconds> <device> <inode> <hex>
+/// <path>`, escaping the path as checksum lines do.
+fn format_entry(path: &Path, n: usize, stamp: &Stamp, digest: &[u8]) -> String {
+    let (prefix, path) = escape_name(&path.to_string_lossy());
+    format!(
+        "{}{} {} {}.{:09} {} {} {} {}",
+        prefix,
+        n,
+        stamp.size,
+        stamp.modified.0,
+        stamp.modified.1,
+        stamp.device,
+        stamp.inode,
+        to_hex(digest),
+        path
+    )
+}
+
+fn parse_entry(line: &str) -> Option<(Key, (Stamp, Vec<u8>))> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let mut fields = line.splitn(7, ' ');
+    let mut next = || fields.next();
+    let n: usize = next()?.parse().ok()?;
+    let size = next()?.parse().ok()?;
+    let (seconds, nanoseconds) = next()?.split_once('.')?;
+    let modified = (seconds.parse().ok()?, nanoseconds.parse().ok()?);
+    let device = next()?.parse().ok()?;
+    let inode = next()?.parse().ok()?;
+    let digest = parse_hex(next()?).filter(|digest| digest.len() * 8 == n)?;
+    let path = next().filter(|path| !path.is_empty())?;
+    let path = if escaped {
+        String::from_utf8(unescape(path.as_bytes())?).ok()?
+    } else {
+        path.to_string()
+    };
+    let stamp = Stamp {
+        size,
+        modified,
+        device,
+        inode,
+    };
+    Some(((PathBuf::from(path), n), (stamp, digest)))
+}
+
+/// Opens the cache a command line asks for.
+///
+/// # Arguments
+///
+/// * `file` - The `--cache` file, if given.
+/// * `disabled` - Whether `--no-cache` was given, which overrides both `--cache` and
+///   `KUPYNASUM_CACHE`.
+///
+/// # Returns
+///
+/// * The cache, or `None` when caching is off.
+pub(crate) fn open(file: Option<&Path>, disabled: bool) -> Option<Cache> {
+    if disabled {
+        return None;
+    }
+    let file = match file {
+        Some(file) => file.to_path_buf(),
+        None => env::var_os(CACHE_VARIABLE)
+            .filter(|file| !file.is_empty())?
+            .into(),
+    };
+    Some(Cache::load(&file))
+}
+
+/// Hashes a file through the cache if there is one.
+pub(crate) fn cached(
+    cache: Option<&Cache>,
+    path: &Path,
+    n: usize,
+    hash: impl FnOnce(&Path) -> io::Result<Vec<u8>>,
+) -> io::Result<Vec<u8>> {
+    match cache {
+        Some(cache) => cache.digest(path, n, hash),
+        None => hash(path),
+    }
+}
+
+/// Saves the cache if there is one, warning rather than failing if it cannot be written.
+pub(crate) fn save(cache: Option<Cache>, stderr: &mut dyn Write) -> io::Result<()> {
+    match cache.map(|cache| cache.save().map_err(|e| (cache.file, e))) {
+        Some(Err((file, e))) => writeln!(
+            stderr,
+            "kupynasum: warning: cannot save cache '{}': {}",
+            file.display(),
+            describe(&e)
+        ),
+        _ => Ok(()),
+    }
+}
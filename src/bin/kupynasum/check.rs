@@ -0,0 +1,313 @@
+//! Verifying lists of checksums, as `sha256sum --check` does.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str;
+
+use kupyna::verify_mac;
+
+use crate::encoding::{os_string, parse_base64, parse_hex};
+use crate::mac::{Mac, MacKind};
+use crate::options::{is_valid_length, Options};
+use crate::sum::{algorithm_prefix, describe, escape_os_name, hash_files};
+
+/// A properly formatted line of a checksum list.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Entry {
+    pub(crate) digest: Vec<u8>,
+    pub(crate) name: OsString,
+}
+
+/// Parses a checksum line: `<digest>  <name>`, `<digest> *<name>`, or the tagged
+/// `KUPYNA-<bits> (<name>) = <digest>`, with the digest in hex or base64.
+///
+/// Lines are bytes, as `sha256sum` reads them, so a name that is not UTF-8 names the same file
+/// it did when the line was written.
+///
+/// # Arguments
+///
+/// * `line` - The line, without its newline.
+/// * `length` - The only digest length in bits to accept, or `None` to take it from the line.
+///
+/// # Returns
+///
+/// * The `Entry`, or `None` if the line is improperly formatted.
+pub(crate) fn parse_line(line: &[u8], length: Option<usize>) -> Option<Entry> {
+    parse_line_with_prefix(line, length, "")
+}
+
+/// Parses a checksum line as `parse_line` does, with `prefix` before `KUPYNA-` in tagged
+/// lines, as in the `HMAC-KUPYNA-256 (<name>) = <code>` lines of `hmac`.
+fn parse_line_with_prefix(line: &[u8], length: Option<usize>, prefix: &str) -> Option<Entry> {
+    let (escaped, line) = match line.strip_prefix(b"\\") {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let tagged = line
+        .strip_prefix(prefix.as_bytes())
+        .and_then(|line| line.strip_prefix(b"KUPYNA-"));
+    let (name, digest) = match tagged {
+        Some(tagged) => {
+            let (bits, rest) = split_once(tagged, b" (")?;
+            let (name, encoded) = rsplit_once(rest, b") = ")?;
+            let bits: usize = str::from_utf8(bits).ok()?.parse().ok()?;
+            if length.is_some_and(|length| length != bits) {
+                return None;
+            }
+            (
+                name,
+                parse_digest(str::from_utf8(encoded).ok()?, Some(bits))?,
+            )
+        }
+        None => {
+            let (encoded, rest) = split_once(line, b" ")?;
+            (
+                rest.strip_prefix(b" ")
+                    .or_else(|| rest.strip_prefix(b"*"))?,
+                parse_digest(str::from_utf8(encoded).ok()?, length)?,
+            )
+        }
+    };
+    if name.is_empty() || !is_valid_length(digest.len() * 8) {
+        return None;
+    }
+    let name = if escaped {
+        unescape(name)?
+    } else {
+        name.to_vec()
+    };
+    Some(Entry {
+        digest,
+        name: os_string(name),
+    })
+}
+
+/// Splits `bytes` around the first `separator`.
+fn split_once<'a>(bytes: &'a [u8], separator: &[u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let at = bytes
+        .windows(separator.len())
+        .position(|w| w == separator)?;
+    Some((&bytes[..at], &bytes[at + separator.len()..]))
+}
+
+/// Splits `bytes` around the last `separator`.
+fn rsplit_once<'a>(bytes: &'a [u8], separator: &[u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let at = bytes
+        .windows(separator.len())
+        .rposition(|w| w == separator)?;
+    Some((&bytes[..at], &bytes[at + separator.len()..]))
+}
+
+/// Splits `bytes` into lines the way `str::lines` splits text.
+fn split_lines(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let pieces = bytes.split(|&b| b == b'\n');
+    // A final newline ends the last line rather than starting another
+    let count = pieces.clone().count() - usize::from(bytes.is_empty() || bytes.ends_with(b"\n"));
+    pieces
+        .take(count)
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Decodes a digest in hex, or failing that in base64, of `bits` bits if given.
+fn parse_digest(encoded: &str, bits: Option<usize>) -> Option<Vec<u8>> {
+    let digest = parse_hex(encoded).or_else(|| parse_base64(encoded))?;
+    match bits {
+        Some(bits) if digest.len() * 8 != bits => {
+            // Hex of one length can also be base64 of another
+            parse_base64(encoded).filter(|digest| digest.len() * 8 == bits)
+        }
+        _ => Some(digest),
+    }
+}
+
+/// Undoes `escape_name`, or returns `None` for a backslash that starts no escape.
+pub(crate) fn unescape(name: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(name.len());
+    let mut bytes = name.iter();
+    while let Some(&b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next()? {
+            b'\\' => out.push(b'\\'),
+            b'n' => out.push(b'\n'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Counts of what happened while checking one list.
+#[derive(Debug, Default)]
+struct Tally {
+    formatted: usize,
+    misformatted: usize,
+    mismatched: usize,
+    unreadable: usize,
+    verified: usize,
+}
+
+/// Checks every list named in `options`, of codes if there is a `mac`.
+///
+/// Digests and codes are compared in constant time, so how long a check takes does not tell
+/// how much of a forged code was right.
+///
+/// # Returns
+///
+/// * Whether every list checked out, or the error that stopped output.
+pub(crate) fn check(
+    options: &Options,
+    mac: Option<&Mac>,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> io::Result<bool> {
+    let mut ok = true;
+    for list in &options.files {
+        ok &= check_list(options, mac, list, stdin, stdout, stderr)?;
+    }
+    Ok(ok)
+}
+
+fn check_list(
+    options: &Options,
+    mac: Option<&Mac>,
+    list: &OsStr,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> io::Result<bool> {
+    let display = if list == "-" {
+        "standard input".into()
+    } else {
+        list.to_string_lossy()
+    };
+    let mut contents = Vec::new();
+    let read = if list == "-" {
+        stdin.read_to_end(&mut contents)
+    } else {
+        File::open(list).and_then(|mut file| file.read_to_end(&mut contents))
+    };
+    if let Err(e) = read {
+        writeln!(
+            stderr,
+            "kupynasum: {}: {}",
+            Path::new(list).display(),
+            describe(&e)
+        )?;
+        return Ok(false);
+    }
+
+    let mut tally = Tally::default();
+    // A KMAC key fixes the length of its codes
+    let length = options.length.or(mac
+        .filter(|mac| mac.kind == MacKind::Kmac)
+        .map(Mac::default_length));
+    let lines: Vec<(usize, Option<Entry>)> = split_lines(&contents)
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with(b"#"))
+        .map(|(index, line)| {
+            let entry = parse_line_with_prefix(line, length, algorithm_prefix(options));
+            (index + 1, entry)
+        })
+        .collect();
+    let files = lines
+        .iter()
+        .map(|(_, entry)| {
+            let entry = entry.as_ref()?;
+            Some((entry.name.as_os_str(), entry.digest.len() * 8))
+        })
+        .collect();
+
+    // Verifying never trusts the cache, since a file can change without its metadata showing it
+    hash_files(files, options.jobs, stdin, None, mac, |index, digest| {
+        let (line, entry) = &lines[index];
+        let (Some(entry), Some(digest)) = (entry, digest) else {
+            tally.misformatted += 1;
+            if options.warn {
+                writeln!(
+                    stderr,
+                    "kupynasum: {}: {}: improperly formatted KUPYNA checksum line",
+                    display, line
+                )?;
+            }
+            return Ok(());
+        };
+        tally.formatted += 1;
+
+        let (prefix, name) = escape_os_name(&entry.name);
+        let mut result = |result: &str| {
+            stdout.write_all(&[prefix.as_bytes(), &name, b": ", result.as_bytes(), b"\n"].concat())
+        };
+        match digest {
+            Err(e) if options.ignore_missing && e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                tally.unreadable += 1;
+                let name = Path::new(&entry.name).display();
+                writeln!(stderr, "kupynasum: {}: {}", name, describe(&e))?;
+                if !options.status {
+                    result("FAILED open or read")?;
+                }
+            }
+            Ok(digest) if verify_mac(&entry.digest, &digest) => {
+                tally.verified += 1;
+                if !options.status && !options.quiet {
+                    result("OK")?;
+                }
+            }
+            Ok(_) => {
+                tally.mismatched += 1;
+                tally.verified += 1;
+                if !options.status {
+                    result("FAILED")?;
+                }
+            }
+        }
+        Ok::<_, io::Error>(())
+    })?;
+
+    if tally.formatted == 0 {
+        writeln!(
+            stderr,
+            "kupynasum: {}: no properly formatted checksum lines found",
+            display
+        )?;
+        return Ok(false);
+    }
+    if !options.status {
+        let warnings = [
+            (
+                tally.misformatted,
+                "line is improperly formatted",
+                "lines are improperly formatted",
+            ),
+            (
+                tally.unreadable,
+                "listed file could not be read",
+                "listed files could not be read",
+            ),
+            (
+                tally.mismatched,
+                "computed checksum did NOT match",
+                "computed checksums did NOT match",
+            ),
+        ];
+        for (count, one, many) in warnings {
+            if count > 0 {
+                let what = if count == 1 { one } else { many };
+                writeln!(stderr, "kupynasum: WARNING: {} {}", count, what)?;
+            }
+        }
+    }
+    if options.ignore_missing && tally.verified == 0 {
+        writeln!(stderr, "kupynasum: {}: no file was verified", display)?;
+        return Ok(false);
+    }
+
+    Ok(tally.mismatched == 0
+        && tally.unreadable == 0
+        && (!options.strict || tally.misformatted == 0))
+}
@@ -0,0 +1,275 @@
+//! Comparing two directory trees by the content of their files.
+//!
+//! Files at the same path are identical or changed according to their digests. A file only in
+//! the left tree and a file only in the right tree with the same digest are reported as one
+//! move. When several files share a digest, they are paired in path order.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cache;
+use crate::encoding::{json_string, to_hex};
+use crate::manifest::hash_tree;
+use crate::options::{parse_jobs, parse_length, Arg, Command, Parser, DEFAULT_LENGTH};
+use crate::sum::escape_name;
+
+pub(crate) const USAGE: &str = "\
+Usage: kupynasum diff [OPTION]... LEFT RIGHT
+Compare the files under two directories by their Kupyna digests.
+
+Each difference is printed as one of:
+  changed: PATH          the file's contents differ
+  left-only: PATH        the file is only under LEFT
+  right-only: PATH       the file is only under RIGHT
+  moved: PATH -> PATH    the same contents are at a new path
+
+      --cache=FILE       reuse the digests of unchanged files recorded in FILE,
+                           and record new ones; the default is $KUPYNASUM_CACHE
+  -j, --jobs=N           hash up to N files at once, or one per CPU if N is 0
+  -l, --length=BITS      digest length in bits, a multiple of 8 from 8 to 512;
+                           the default is 256
+      --no-cache         hash every file, even with --cache or $KUPYNASUM_CACHE
+  -s, --identical        also print 'identical: PATH' for files that match
+      --json             print one JSON object per file instead
+  -h, --help             display this help and exit
+
+Exit status is 0 if the trees have the same files, 1 if they differ, and 2 if
+there was trouble.
+";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct DiffOptions {
+    /// The digest length in bits.
+    pub(crate) length: usize,
+    pub(crate) identical: bool,
+    pub(crate) json: bool,
+    /// The digest cache file, if given.
+    pub(crate) cache: Option<PathBuf>,
+    pub(crate) no_cache: bool,
+    /// The most files to hash at once.
+    pub(crate) jobs: usize,
+    pub(crate) left: PathBuf,
+    pub(crate) right: PathBuf,
+}
+
+const SHORT: &[(char, &str)] = &[
+    ('j', "jobs"),
+    ('l', "length"),
+    ('s', "identical"),
+    ('h', "help"),
+];
+
+/// Parses the arguments after `diff`.
+pub(crate) fn parse<I: Iterator<Item = OsString>>(args: I) -> Result<Command, String> {
+    let mut options = DiffOptions {
+        length: DEFAULT_LENGTH,
+        ..DiffOptions::default()
+    };
+    let mut dirs = Vec::new();
+    let mut parser = Parser::new(args, SHORT);
+    while let Some(arg) = parser.next()? {
+        let name = match arg {
+            Arg::Operand(dir) => {
+                dirs.push(dir);
+                continue;
+            }
+            Arg::Option(name) => name,
+        };
+        match name.as_str() {
+            "length" => options.length = parse_length(&parser.text_value()?)?,
+            "identical" => options.identical = true,
+            "json" => options.json = true,
+            "cache" => options.cache = Some(parser.value()?.into()),
+            "no-cache" => options.no_cache = true,
+            "jobs" => options.jobs = parse_jobs(&parser.text_value()?)?,
+            "help" => return Ok(Command::Help(USAGE)),
+            _ => return Err(parser.unknown()),
+        }
+    }
+
+    [options.left, options.right] = match <[OsString; 2]>::try_from(dirs) {
+        Ok(dirs) => dirs.map(PathBuf::from),
+        Err(dirs) if dirs.len() > 2 => {
+            return Err(format!("extra operand '{}'", dirs[2].to_string_lossy()))
+        }
+        Err(_) => return Err("two directory operands are required".into()),
+    };
+    Ok(Command::Diff(options))
+}
+
+/// How a file compares between the two trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Difference {
+    Identical(String),
+    Changed(String),
+    LeftOnly(String),
+    RightOnly(String),
+    /// The same contents at the left path and the right path.
+    Moved(String, String),
+}
+
+impl Difference {
+    /// The path the difference is sorted by: the left one where there is one.
+    fn path(&self) -> &str {
+        match self {
+            Difference::Identical(path)
+            | Difference::Changed(path)
+            | Difference::LeftOnly(path)
+            | Difference::RightOnly(path)
+            | Difference::Moved(path, _) => path,
+        }
+    }
+}
+
+/// A file's digest in each tree, where it is there.
+pub(crate) type Digests<'a> = (Option<&'a [u8]>, Option<&'a [u8]>);
+
+/// Compares two trees' files.
+///
+/// # Arguments
+///
+/// * `left`, `right` - Each tree's relative paths and digests, as `hash_tree` returns them.
+///
+/// # Returns
+///
+/// * Every file's `Difference` with its digests, sorted by path.
+pub(crate) fn compare<'a>(
+    left: &'a [(String, Vec<u8>)],
+    right: &'a [(String, Vec<u8>)],
+) -> Vec<(Difference, Digests<'a>)> {
+    let mut paths: BTreeMap<&str, Digests> = BTreeMap::new();
+    for (path, digest) in left {
+        paths.entry(path).or_default().0 = Some(digest);
+    }
+    for (path, digest) in right {
+        paths.entry(path).or_default().1 = Some(digest);
+    }
+
+    // Right-only paths by digest, each list in path order, for pairing with left-only ones
+    let mut arrivals: HashMap<&[u8], Vec<&str>> = HashMap::new();
+    for (path, digests) in paths.iter().rev() {
+        if let (None, Some(digest)) = digests {
+            arrivals.entry(digest).or_default().push(path);
+        }
+    }
+
+    let mut differences = Vec::new();
+    let mut moved_to = HashSet::new();
+    for (&path, &digests) in &paths {
+        let difference = match digests {
+            (Some(l), Some(r)) if l == r => Difference::Identical(path.to_string()),
+            (Some(_), Some(_)) => Difference::Changed(path.to_string()),
+            (Some(l), None) => match arrivals.get_mut(l).and_then(Vec::pop) {
+                Some(to) => {
+                    moved_to.insert(to);
+                    differences.push((
+                        Difference::Moved(path.to_string(), to.to_string()),
+                        (Some(l), Some(l)),
+                    ));
+                    continue;
+                }
+                None => Difference::LeftOnly(path.to_string()),
+            },
+            (None, _) => continue,
+        };
+        differences.push((difference, digests));
+    }
+    for (&path, &digests) in &paths {
+        if digests.0.is_none() && !moved_to.contains(&path) {
+            differences.push((Difference::RightOnly(path.to_string()), digests));
+        }
+    }
+    differences.sort_by(|a, b| a.0.path().cmp(b.0.path()));
+    differences
+}
+
+/// Runs the `diff` subcommand.
+///
+/// # Returns
+///
+/// * The exit status, or the error that stopped output.
+pub(crate) fn run(
+    options: &DiffOptions,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> io::Result<i32> {
+    let cache = cache::open(options.cache.as_deref(), options.no_cache);
+    let hash = |dir: &Path| hash_tree(dir, options.length, options.jobs, cache.as_ref());
+    let trees = hash(&options.left).and_then(|left| {
+        let right = hash(&options.right)?;
+        Ok((left, right))
+    });
+    cache::save(cache, stderr)?;
+    let (left, right) = match trees {
+        Ok(trees) => trees,
+        Err(message) => {
+            writeln!(stderr, "kupynasum: {}", message)?;
+            return Ok(2);
+        }
+    };
+
+    let mut same = true;
+    for (difference, digests) in compare(&left, &right) {
+        let identical = matches!(difference, Difference::Identical(_));
+        same &= identical;
+        if identical && !options.identical {
+            continue;
+        }
+        let line = if options.json {
+            json_line(&difference, digests)
+        } else {
+            text_line(&difference)
+        };
+        writeln!(stdout, "{}", line)?;
+    }
+    Ok(if same { 0 } else { 1 })
+}
+
+fn text_line(difference: &Difference) -> String {
+    let (status, path) = match difference {
+        Difference::Identical(path) => ("identical", path),
+        Difference::Changed(path) => ("changed", path),
+        Difference::LeftOnly(path) => ("left-only", path),
+        Difference::RightOnly(path) => ("right-only", path),
+        Difference::Moved(from, to) => {
+            let (from_prefix, from) = escape_name(from);
+            let (to_prefix, to) = escape_name(to);
+            let prefix = if from_prefix.is_empty() {
+                to_prefix
+            } else {
+                from_prefix
+            };
+            return format!("{}moved: {} -> {}", prefix, from, to);
+        }
+    };
+    let (prefix, path) = escape_name(path);
+    format!("{}{}: {}", prefix, status, path)
+}
+
+/// Formats a difference as `{"status":…,"left":…,"right":…}`, where each side is `null` or an
+/// object with the file's path and digest.
+fn json_line(difference: &Difference, (left, right): Digests) -> String {
+    let (status, left_path, right_path) = match difference {
+        Difference::Identical(path) => ("identical", path, path),
+        Difference::Changed(path) => ("changed", path, path),
+        Difference::LeftOnly(path) => ("left-only", path, path),
+        Difference::RightOnly(path) => ("right-only", path, path),
+        Difference::Moved(from, to) => ("moved", from, to),
+    };
+    let side = |path: &str, digest: Option<&[u8]>| match digest {
+        Some(digest) => format!(
+            "{{\"path\":{},\"digest\":{}}}",
+            json_string(path),
+            json_string(&to_hex(digest))
+        ),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"status\":{},\"left\":{},\"right\":{}}}",
+        json_string(status),
+        side(left_path, left),
+        side(right_path, right)
+    )
+}
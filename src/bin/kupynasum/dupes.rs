@@ -0,0 +1,344 @@
+//! Finding files with the same contents.
+//!
+//! Files are narrowed down in three passes, each only over the files the last one left in a
+//! group of two or more: first by size, then by the digest of their first `PREFIX_LEN` bytes,
+//! and last by the digest of the whole file. Most files are ruled out by size alone, and most
+//! of the rest after reading one block, so few are read in full.
+//!
+//! Empty files are skipped, as are symbolic links. On Unix, paths that are already hard links
+//! to the same file count once, so linking a set leaves nothing to report next time.
+//!
+//! Matching digests are enough to report files, but not to link them: each duplicate is
+//! compared byte for byte with the file it would be linked to, and neither may have changed
+//! since, so a digest collision or a file written meanwhile never loses data.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use kupyna::kupyna_hash_file;
+
+use crate::cache::{self, cached, Cache, Stamp};
+use crate::manifest::walk;
+use crate::options::{parse_jobs, parse_length, Arg, Command, Options, Parser, DEFAULT_LENGTH};
+use crate::pool::map_ordered;
+use crate::sum::{describe, format_line, hash_reader};
+
+pub(crate) const USAGE: &str = "\
+Usage: kupynasum dupes [OPTION]... DIR...
+Find files with the same contents under the DIRs, by their Kupyna digests.
+
+Each set of duplicates is printed as checksum lines in path order, followed by
+an empty line.
+
+      --cache=FILE       reuse the digests of unchanged files recorded in FILE,
+                           and record new ones; the default is $KUPYNASUM_CACHE
+  -j, --jobs=N           hash up to N files at once, or one per CPU if N is 0
+  -l, --length=BITS      digest length in bits, a multiple of 8 from 8 to 512;
+                           the default is 256
+  -L, --link             replace every file in a set with a hard link to the
+                           first one, after comparing their contents; needs
+                           a length of at least 256
+      --no-cache         hash every file, even with --cache or $KUPYNASUM_CACHE
+  -h, --help             display this help and exit
+";
+
+/// How much of each file the second pass hashes.
+pub(crate) const PREFIX_LEN: u64 = 4096;
+
+/// The shortest digest, in bits, that `--link` trusts to find candidates worth comparing.
+pub(crate) const LINK_MIN_LENGTH: usize = 256;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct DupesOptions {
+    /// The digest length in bits.
+    pub(crate) length: usize,
+    pub(crate) link: bool,
+    /// The digest cache file, if given.
+    pub(crate) cache: Option<PathBuf>,
+    pub(crate) no_cache: bool,
+    /// The most files to hash at once.
+    pub(crate) jobs: usize,
+    pub(crate) dirs: Vec<PathBuf>,
+}
+
+const SHORT: &[(char, &str)] = &[('j', "jobs"), ('l', "length"), ('L', "link"), ('h', "help")];
+
+/// Parses the arguments after `dupes`.
+pub(crate) fn parse<I: Iterator<Item = OsString>>(args: I) -> Result<Command, String> {
+    let mut options = DupesOptions {
+        length: DEFAULT_LENGTH,
+        ..DupesOptions::default()
+    };
+    let mut parser = Parser::new(args, SHORT);
+    while let Some(arg) = parser.next()? {
+        let name = match arg {
+            Arg::Operand(dir) => {
+                options.dirs.push(dir.into());
+                continue;
+            }
+            Arg::Option(name) => name,
+        };
+        match name.as_str() {
+            "length" => options.length = parse_length(&parser.text_value()?)?,
+            "link" => options.link = true,
+            "cache" => options.cache = Some(parser.value()?.into()),
+            "no-cache" => options.no_cache = true,
+            "jobs" => options.jobs = parse_jobs(&parser.text_value()?)?,
+            "help" => return Ok(Command::Help(USAGE)),
+            _ => return Err(parser.unknown()),
+        }
+    }
+    if options.dirs.is_empty() {
+        return Err("missing directory operand".into());
+    }
+    if options.link && options.length < LINK_MIN_LENGTH {
+        return Err(format!(
+            "--link needs a length of at least {} bits",
+            LINK_MIN_LENGTH
+        ));
+    }
+    Ok(Command::Dupes(options))
+}
+
+/// A set of files with the same contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DuplicateSet {
+    pub(crate) digest: Vec<u8>,
+    /// The files, in path order.
+    pub(crate) paths: Vec<PathBuf>,
+}
+
+/// Groups files with the same contents.
+///
+/// # Arguments
+///
+/// * `files` - The files to compare.
+/// * `n` - The length of the digests in bits.
+/// * `jobs` - The most files to hash at once.
+/// * `cache` - The digest cache, if any; only whole-file digests go in it.
+/// * `errors` - Collects a message for every file that could not be read; those files are
+///   left out.
+///
+/// # Returns
+///
+/// * Every set of two or more files with the same digest, sorted by their first path.
+pub(crate) fn find_duplicates(
+    files: Vec<PathBuf>,
+    n: usize,
+    jobs: usize,
+    cache: Option<&Cache>,
+    errors: &mut Vec<String>,
+) -> Vec<DuplicateSet> {
+    let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+    let mut seen = HashSet::new();
+    for path in files {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if !metadata.is_file() || metadata.len() == 0 => {}
+            // A second name for a file already listed is not a duplicate
+            Ok(metadata) if file_id(&metadata).is_some_and(|id| !seen.insert(id)) => {}
+            Ok(metadata) => by_size.entry(metadata.len()).or_default().push(path),
+            Err(e) => errors.push(format!("{}: {}", path.display(), describe(&e))),
+        }
+    }
+
+    let prefix = |path: &Path| File::open(path).and_then(|f| hash_reader(f.take(PREFIX_LEN), n));
+    let by_prefix = regroup(by_size, jobs, prefix, errors);
+    // Where the first block was the whole file, its digest is already the full one
+    let (whole, partial): (Vec<_>, Vec<_>) = by_prefix
+        .into_iter()
+        .partition(|((size, _), _)| *size <= PREFIX_LEN);
+    let full = |path: &Path| cached(cache, path, n, |path| kupyna_hash_file(path, n));
+    let by_digest = regroup(partial, jobs, full, errors);
+
+    let mut sets: Vec<DuplicateSet> = whole
+        .into_iter()
+        .map(|((_, digest), paths)| DuplicateSet { digest, paths })
+        .chain(
+            by_digest
+                .into_iter()
+                .map(|((_, digest), paths)| DuplicateSet { digest, paths }),
+        )
+        .collect();
+    for set in &mut sets {
+        set.paths.sort();
+    }
+    sets.sort_by(|a, b| a.paths[0].cmp(&b.paths[0]));
+    sets
+}
+
+/// Splits groups of files by a digest, keeping only the new groups of two or more.
+///
+/// # Arguments
+///
+/// * `groups` - The groups to split, by key.
+/// * `jobs` - The most files to hash at once.
+/// * `digest` - The digest to split by.
+/// * `errors` - Collects a message for every file that could not be read.
+///
+/// # Returns
+///
+/// * The new groups, by their old key and their digest.
+fn regroup<K: Ord + Clone + Send>(
+    groups: impl IntoIterator<Item = (K, Vec<PathBuf>)>,
+    jobs: usize,
+    digest: impl Fn(&Path) -> io::Result<Vec<u8>> + Sync,
+    errors: &mut Vec<String>,
+) -> BTreeMap<(K, Vec<u8>), Vec<PathBuf>> {
+    let items: Vec<(K, PathBuf)> = groups
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(key, paths)| paths.into_iter().map(move |path| (key.clone(), path)))
+        .collect();
+    let mut regrouped: BTreeMap<(K, Vec<u8>), Vec<PathBuf>> = BTreeMap::new();
+    let work = |(key, path): (K, PathBuf)| {
+        let result = digest(&path);
+        (key, path, result)
+    };
+    let _ = map_ordered(items, jobs, work, |(key, path, result)| {
+        match result {
+            Ok(digest) => regrouped.entry((key, digest)).or_default().push(path),
+            Err(e) => errors.push(format!("{}: {}", path.display(), describe(&e))),
+        }
+        Ok::<_, ()>(())
+    });
+    regrouped.retain(|_, paths| paths.len() > 1);
+    regrouped
+}
+
+/// The device and inode that identify a file, where the platform has them.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Whether two files have the same contents, compared byte for byte.
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut block_a, mut block_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let len = read_full(&mut a, &mut block_a)?;
+        if len != read_full(&mut b, &mut block_b)? || block_a[..len] != block_b[..len] {
+            return Ok(false);
+        }
+        if len == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Reads until `buffer` is full or the reader ends, returning how much was read.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+/// The stamps of two files, which must both have one.
+fn stamps(a: &Path, b: &Path) -> io::Result<(Stamp, Stamp)> {
+    let stamp = |path: &Path| {
+        Stamp::of(&fs::metadata(path)?)
+            .ok_or_else(|| io::Error::other("no modification time to check it against"))
+    };
+    Ok((stamp(a)?, stamp(b)?))
+}
+
+/// Replaces `duplicate` with a hard link to `original`, if they have the same contents.
+///
+/// The files are compared byte for byte first, so files whose digests merely collide are left
+/// alone. The link is made under a temporary name in the same directory, and renamed over
+/// `duplicate` only if the size, modification time and inode of both files are still as they
+/// were when compared. `duplicate` never goes missing, even if linking fails part-way.
+pub(crate) fn link(original: &Path, duplicate: &Path) -> io::Result<()> {
+    let name = duplicate
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?;
+    let before = stamps(original, duplicate)?;
+    if !same_contents(original, duplicate)? {
+        return Err(io::Error::other("contents differ"));
+    }
+    let temporary = duplicate.with_file_name(format!(".{}.kupynasum-link", name.to_string_lossy()));
+    fs::hard_link(original, &temporary)?;
+    let renamed = match stamps(original, duplicate) {
+        Ok(after) if after == before => fs::rename(&temporary, duplicate),
+        Ok(_) => Err(io::Error::other("file changed while linking")),
+        Err(e) => Err(e),
+    };
+    renamed.inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+/// Runs the `dupes` subcommand.
+///
+/// # Returns
+///
+/// * Whether every file could be read and every link made, or the error that stopped output.
+pub(crate) fn run(
+    options: &DupesOptions,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> io::Result<bool> {
+    let mut errors = Vec::new();
+    let mut files = Vec::new();
+    for dir in &options.dirs {
+        let root = dir.as_path();
+        match walk(root) {
+            Ok(paths) => files.extend(paths.iter().map(|path| root.join(path))),
+            Err(message) => errors.push(message),
+        }
+    }
+    // Linking destroys the duplicates, so it acts only on digests of what is there now
+    let cache = cache::open(options.cache.as_deref(), options.no_cache || options.link);
+    let sets = find_duplicates(
+        files,
+        options.length,
+        options.jobs,
+        cache.as_ref(),
+        &mut errors,
+    );
+    cache::save(cache, stderr)?;
+
+    for set in &sets {
+        for path in &set.paths {
+            let mut line = format_line(&Options::default(), &set.digest, path.as_os_str());
+            line.push(b'\n');
+            stdout.write_all(&line)?;
+        }
+        writeln!(stdout)?;
+    }
+    if options.link {
+        for set in &sets {
+            for duplicate in &set.paths[1..] {
+                if let Err(e) = link(&set.paths[0], duplicate) {
+                    errors.push(format!(
+                        "cannot link '{}' to '{}': {}",
+                        duplicate.display(),
+                        set.paths[0].display(),
+                        describe(&e)
+                    ));
+                }
+            }
+        }
+    }
+
+    for message in &errors {
+        writeln!(stderr, "kupynasum: {}", message)?;
+    }
+    Ok(errors.is_empty())
+}
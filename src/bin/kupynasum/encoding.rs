@@ -0,0 +1,107 @@
+//! Text encodings for digests: hex, base64, and JSON strings, and file names as bytes.
+
+use std::ffi::OsString;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as lowercase hex.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes hex of either case, or returns `None` if `hex` has any other character or an odd
+/// length.
+pub(crate) fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Encodes bytes as padded base64 with the standard alphabet (RFC 4648, section 4).
+pub(crate) fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &b)| group | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(group >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes padded base64 with the standard alphabet, or returns `None` if `text` is not in
+/// its canonical encoding.
+pub(crate) fn parse_base64(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() || !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let groups = text.as_bytes().chunks(4);
+    let last = groups.len() - 1;
+    for (index, group) in groups.enumerate() {
+        let padding = group.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && index != last) {
+            return None;
+        }
+        let mut bits = 0u32;
+        for &c in &group[..4 - padding] {
+            let value = BASE64_ALPHABET.iter().position(|&a| a == c)?;
+            bits = bits << 6 | value as u32;
+        }
+        bits <<= 6 * padding;
+        let bytes = bits.to_be_bytes();
+        let len = 3 - padding;
+        // Bits below the last whole byte must be zero for the encoding to be canonical
+        if bytes[1 + len..].iter().any(|&b| b != 0) {
+            return None;
+        }
+        out.extend_from_slice(&bytes[1..1 + len]);
+    }
+    Some(out)
+}
+
+/// Quotes a string as a JSON string literal.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The file name spelled by the bytes of an argument or a checksum line.
+///
+/// On Unix names are bytes, so these are the name's bytes exactly, as `OsStr::as_encoded_bytes`
+/// gives them. Elsewhere names are Unicode, and bytes that are not UTF-8 cannot name a file.
+#[cfg(unix)]
+pub(crate) fn os_string(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn os_string(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
@@ -0,0 +1,196 @@
+//! Deriving keys with HKDF-Kupyna and PBKDF2-Kupyna.
+//!
+//! The secret is read from standard input or a file, never from the command line, and is used
+//! exactly as read, final newline and all. Salts and info are not secret, so they are taken as
+//! arguments.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use kupyna::{hkdf_kupyna, pbkdf2_kupyna};
+
+use crate::encoding::{parse_hex, to_base64, to_hex};
+use crate::options::{parse_length, Arg, Command, Parser, DEFAULT_LENGTH};
+use crate::sum::describe;
+
+pub(crate) const USAGE: &str = "\
+Usage: kupynasum hkdf [OPTION]...
+  or:  kupynasum pbkdf2 --iterations=N [OPTION]...
+Derive a key from a secret with HKDF-Kupyna (RFC 5869) or PBKDF2-Kupyna
+(RFC 8018), using HMAC-Kupyna.
+
+The secret is read from standard input, or from --secret-file, and used exactly
+as read, including any final newline.
+
+      --secret-file=FILE  read the secret from FILE
+      --salt=TEXT         the salt; the default is none
+      --salt-hex=HEX      the salt, in hex
+      --info=TEXT         HKDF only: context binding the key to its use
+      --info-hex=HEX      HKDF only: the context, in hex
+  -i, --iterations=N      PBKDF2 only: how many times to apply HMAC; required
+  -n, --bytes=N           derive N bytes; the default is 32
+  -l, --length=BITS       hash code length in bits, a multiple of 8 from 8 to
+                            512; the default is 256
+      --base64            print the key in base64 rather than hex
+      --raw               write the key as raw bytes, without a newline
+  -h, --help              display this help and exit
+";
+
+/// Which key derivation function to use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum KdfKind {
+    #[default]
+    Hkdf,
+    Pbkdf2,
+}
+
+/// How the derived key is printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    #[default]
+    Hex,
+    Base64,
+    Raw,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct KdfOptions {
+    pub(crate) kind: KdfKind,
+    /// The hash code length in bits.
+    pub(crate) length: usize,
+    /// The length of the derived key in bytes.
+    pub(crate) bytes: usize,
+    pub(crate) salt: Vec<u8>,
+    pub(crate) info: Vec<u8>,
+    pub(crate) iterations: u32,
+    /// The file holding the secret, or `-` for standard input.
+    pub(crate) secret: OsString,
+    pub(crate) encoding: Encoding,
+}
+
+const SHORT: &[(char, &str)] = &[
+    ('i', "iterations"),
+    ('n', "bytes"),
+    ('l', "length"),
+    ('h', "help"),
+];
+
+/// Parses the arguments after `hkdf` or `pbkdf2`.
+pub(crate) fn parse<I: Iterator<Item = OsString>>(
+    args: I,
+    kind: KdfKind,
+) -> Result<Command, String> {
+    let mut options = KdfOptions {
+        kind,
+        length: DEFAULT_LENGTH,
+        bytes: 32,
+        secret: "-".into(),
+        ..KdfOptions::default()
+    };
+    let hkdf = kind == KdfKind::Hkdf;
+    let mut parser = Parser::new(args, SHORT);
+    while let Some(arg) = parser.next()? {
+        let name = match arg {
+            Arg::Operand(operand) => {
+                return Err(format!(
+                    "extra operand '{}'; the secret is read from standard input or --secret-file",
+                    operand.to_string_lossy()
+                ))
+            }
+            Arg::Option(name) => name,
+        };
+        match name.as_str() {
+            "secret-file" => options.secret = parser.value()?,
+            "salt" => options.salt = parser.value()?.into_encoded_bytes(),
+            "salt-hex" => options.salt = parse_hex_value("salt", &parser.text_value()?)?,
+            "info" if hkdf => options.info = parser.value()?.into_encoded_bytes(),
+            "info-hex" if hkdf => options.info = parse_hex_value("info", &parser.text_value()?)?,
+            "iterations" if !hkdf => {
+                let value = parser.text_value()?;
+                options.iterations = match value.parse() {
+                    Ok(iterations) if iterations > 0 => iterations,
+                    _ => return Err(format!("invalid number of iterations: '{}'", value)),
+                };
+            }
+            "bytes" => {
+                let value = parser.text_value()?;
+                options.bytes = match value.parse() {
+                    Ok(bytes) if bytes > 0 => bytes,
+                    _ => return Err(format!("invalid number of bytes: '{}'", value)),
+                };
+            }
+            "length" => options.length = parse_length(&parser.text_value()?)?,
+            "base64" => set_encoding(&mut options, Encoding::Base64)?,
+            "raw" => set_encoding(&mut options, Encoding::Raw)?,
+            "help" => return Ok(Command::Help(USAGE)),
+            _ => return Err(parser.unknown()),
+        }
+    }
+    if !hkdf && options.iterations == 0 {
+        return Err("--iterations is required".into());
+    }
+    Ok(Command::Kdf(options))
+}
+
+fn parse_hex_value(what: &str, value: &str) -> Result<Vec<u8>, String> {
+    parse_hex(value).ok_or_else(|| format!("invalid hex {}: '{}'", what, value))
+}
+
+fn set_encoding(options: &mut KdfOptions, encoding: Encoding) -> Result<(), String> {
+    if options.encoding != Encoding::Hex && options.encoding != encoding {
+        return Err("--base64 and --raw are mutually exclusive".into());
+    }
+    options.encoding = encoding;
+    Ok(())
+}
+
+/// Runs the `hkdf` or `pbkdf2` subcommand.
+///
+/// # Returns
+///
+/// * Whether a key was derived, or the error that stopped output.
+pub(crate) fn run(
+    options: &KdfOptions,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> io::Result<bool> {
+    let mut secret = Vec::new();
+    let read = if options.secret == "-" {
+        stdin.read_to_end(&mut secret)
+    } else {
+        File::open(&options.secret).and_then(|mut file| file.read_to_end(&mut secret))
+    };
+    let display = if options.secret == "-" {
+        "standard input".into()
+    } else {
+        options.secret.to_string_lossy()
+    };
+    if let Err(e) = read {
+        writeln!(stderr, "kupynasum: {}: {}", display, describe(&e))?;
+        return Ok(false);
+    }
+    if secret.is_empty() {
+        writeln!(stderr, "kupynasum: {}: secret is empty", display)?;
+        return Ok(false);
+    }
+
+    let (n, len) = (options.length, options.bytes);
+    let key = match options.kind {
+        KdfKind::Hkdf => match hkdf_kupyna(&secret, &options.salt, &options.info, len, n) {
+            Ok(key) => key,
+            Err(e) => {
+                writeln!(stderr, "kupynasum: {}", e)?;
+                return Ok(false);
+            }
+        },
+        KdfKind::Pbkdf2 => pbkdf2_kupyna(&secret, &options.salt, options.iterations, len, n),
+    };
+    match options.encoding {
+        Encoding::Hex => writeln!(stdout, "{}", to_hex(&key))?,
+        Encoding::Base64 => writeln!(stdout, "{}", to_base64(&key))?,
+        Encoding::Raw => stdout.write_all(&key)?,
+    }
+    Ok(true)
+}
@@ -0,0 +1,176 @@
+//! Keyed digests: HMAC-Kupyna and KMAC-Kupyna codes of files, and checking them.
+//!
+//! The `hmac` and `kmac` subcommands take the same options as plain hashing and print and
+//! check the same lines, with each file's code in place of its digest. Keys are read from a
+//! file or an environment variable, never from the command line, where other users can see
+//! them in the process list and they end up in shell history.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use kupyna::{HmacKupyna, KmacKupyna};
+
+use crate::encoding::parse_hex;
+use crate::sum::{describe, read_chunks};
+
+pub(crate) const USAGE: &str = "\
+Usage: kupynasum hmac [OPTION]... [FILE]...
+  or:  kupynasum kmac [OPTION]... [FILE]...
+Print or check HMAC-Kupyna or KMAC-Kupyna codes (DSTU 7564).
+
+With no FILE, or when FILE is -, read standard input. Exactly one of
+--key-file and --key-env gives the key; keys are never taken as arguments.
+
+      --key-file=FILE   read the key from FILE
+      --key-env=NAME    read the key from the environment variable NAME
+      --hex-key         the key is written in hex, rather than used as it is
+  -c, --check           read codes from the FILEs and check them
+  -j, --jobs=N          hash up to N files at once, or one per CPU if N is 0
+  -l, --length=BITS     code length in bits, a multiple of 8 from 8 to 512;
+                          the default is 256 for HMAC, and the key's length for
+                          KMAC, which allows only 256, 384 and 512
+      --tag             create a BSD-style line, HMAC-KUPYNA-256 (FILE) = CODE
+      --json            create JSON lines with the file, algorithm and code
+      --base64          print codes in base64 rather than hex
+  -h, --help            display this help and exit
+
+The verification options of plain hashing work as they do there. Codes are
+compared in constant time, and the exit status is non-zero if any differs.
+";
+
+/// Which MAC to compute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MacKind {
+    Hmac,
+    Kmac,
+}
+
+impl MacKind {
+    /// The prefix of the algorithm name in tagged and JSON lines.
+    pub(crate) fn prefix(self) -> &'static str {
+        match self {
+            MacKind::Hmac => "HMAC-",
+            MacKind::Kmac => "KMAC-",
+        }
+    }
+}
+
+/// Where the key comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum KeySource {
+    File(PathBuf),
+    Env(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MacOptions {
+    pub(crate) kind: MacKind,
+    pub(crate) key: KeySource,
+    /// Whether the key is written in hex.
+    pub(crate) hex_key: bool,
+}
+
+/// A MAC with its key loaded.
+#[derive(Debug)]
+pub(crate) struct Mac {
+    pub(crate) kind: MacKind,
+    key: Vec<u8>,
+}
+
+impl Mac {
+    /// Reads the key `options` name.
+    ///
+    /// # Returns
+    ///
+    /// * The MAC, or a message saying why the key could not be read or is unusable.
+    pub(crate) fn load(options: &MacOptions) -> Result<Mac, String> {
+        let (key, source) = match &options.key {
+            KeySource::File(file) => (
+                fs::read(file).map_err(|e| format!("{}: {}", file.display(), describe(&e)))?,
+                file.display().to_string(),
+            ),
+            KeySource::Env(name) => {
+                let value = env::var_os(name)
+                    .ok_or_else(|| format!("environment variable {} is not set", name))?;
+                let value = value
+                    .into_string()
+                    .map_err(|_| format!("environment variable {} is not valid UTF-8", name))?;
+                (value.into_bytes(), format!("environment variable {}", name))
+            }
+        };
+        let key = if options.hex_key {
+            std::str::from_utf8(&key)
+                .ok()
+                .and_then(|text| parse_hex(text.trim()))
+                .ok_or_else(|| format!("{}: key is not valid hex", source))?
+        } else {
+            key
+        };
+        if key.is_empty() {
+            return Err(format!("{}: key is empty", source));
+        }
+        if options.kind == MacKind::Kmac && ![32, 48, 64].contains(&key.len()) {
+            return Err(format!(
+                "{}: KMAC keys are 32, 48 or 64 bytes, not {}",
+                source,
+                key.len()
+            ));
+        }
+        Ok(Mac {
+            kind: options.kind,
+            key,
+        })
+    }
+
+    /// The code length to use when none is given.
+    pub(crate) fn default_length(&self) -> usize {
+        match self.kind {
+            MacKind::Hmac => 256,
+            MacKind::Kmac => self.key.len() * 8,
+        }
+    }
+
+    /// Checks that the key can make `n`-bit codes.
+    ///
+    /// # Returns
+    ///
+    /// * A message saying why not, if it cannot.
+    pub(crate) fn check_length(&self, n: usize) -> Result<(), String> {
+        match self.kind {
+            MacKind::Hmac => Ok(()),
+            MacKind::Kmac => KmacKupyna::new(&self.key, n)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Computes the `n`-bit code of everything `reader` yields.
+    ///
+    /// # Returns
+    ///
+    /// * The code, or the first read error. A length the key cannot make is an
+    ///   `InvalidInput` error.
+    pub(crate) fn code_reader<R: Read>(&self, reader: R, n: usize) -> io::Result<Vec<u8>> {
+        enum State {
+            Hmac(HmacKupyna),
+            Kmac(KmacKupyna),
+        }
+        let mut state = match self.kind {
+            MacKind::Hmac => State::Hmac(HmacKupyna::new(&self.key, n)),
+            MacKind::Kmac => State::Kmac(
+                KmacKupyna::new(&self.key, n)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+            ),
+        };
+        read_chunks(reader, |chunk| match &mut state {
+            State::Hmac(mac) => mac.update(chunk),
+            State::Kmac(mac) => mac.update(chunk),
+        })?;
+        Ok(match state {
+            State::Hmac(mac) => mac.finalize(),
+            State::Kmac(mac) => mac.finalize(),
+        })
+    }
+}
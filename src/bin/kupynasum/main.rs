@@ -0,0 +1,114 @@
+//! `kupynasum`: print or check Kupyna checksums, with the interface of coreutils `sha256sum`.
+
+use std::ffi::OsString;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+
+use mac::Mac;
+use options::Command;
+
+mod cache;
+mod check;
+mod diff;
+mod dupes;
+mod encoding;
+mod kdf;
+mod mac;
+mod manifest;
+mod options;
+mod pool;
+mod sum;
+#[cfg(test)]
+mod tests;
+
+fn main() {
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    let mut code = run(
+        std::env::args_os().skip(1),
+        &mut io::stdin().lock(),
+        &mut stdout,
+        &mut io::stderr(),
+    );
+    if stdout.flush().is_err() {
+        code = 1;
+    }
+    process::exit(code);
+}
+
+/// Runs the command line and returns the exit status.
+///
+/// # Arguments
+///
+/// * `args` - The arguments, without the program name. They need not be valid UTF-8.
+/// * `stdin`, `stdout`, `stderr` - The standard streams.
+///
+/// # Returns
+///
+/// * 0 on success, or 1 if any file failed or the arguments were bad, as `sha256sum` does.
+pub(crate) fn run<I: IntoIterator<Item = OsString>>(
+    args: I,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let options = match options::parse(args) {
+        Ok(Command::Sum(options)) => options,
+        Ok(Command::Manifest(options)) => {
+            return report(manifest::run(&options, stdin, stdout, stderr), stderr)
+        }
+        Ok(Command::Dupes(options)) => return report(dupes::run(&options, stdout, stderr), stderr),
+        Ok(Command::Kdf(options)) => {
+            return report(kdf::run(&options, stdin, stdout, stderr), stderr)
+        }
+        Ok(Command::Diff(options)) => {
+            return match diff::run(&options, stdout, stderr) {
+                Ok(code) => code,
+                Err(e) => {
+                    report(Err(e), stderr);
+                    2
+                }
+            };
+        }
+        Ok(Command::Help(usage)) => {
+            return status(stdout.write_all(usage.as_bytes()).map(|_| true))
+        }
+        Ok(Command::Version) => {
+            let version = writeln!(stdout, "kupynasum {}", env!("CARGO_PKG_VERSION"));
+            return status(version.map(|_| true));
+        }
+        Err(message) => {
+            let _ = writeln!(stderr, "kupynasum: {}", message);
+            let _ = writeln!(stderr, "Try 'kupynasum --help' for more information.");
+            return 1;
+        }
+    };
+
+    let mac = match options.mac.as_ref().map(Mac::load).transpose() {
+        Ok(mac) => mac,
+        Err(message) => {
+            let _ = writeln!(stderr, "kupynasum: {}", message);
+            return 1;
+        }
+    };
+    let result = if options.check {
+        check::check(&options, mac.as_ref(), stdin, stdout, stderr)
+    } else {
+        sum::sum(&options, mac.as_ref(), stdin, stdout, stderr)
+    };
+    report(result, stderr)
+}
+
+/// Reports an error that stopped output, and returns the exit status.
+fn report(result: io::Result<bool>, stderr: &mut dyn Write) -> i32 {
+    if let Err(e) = &result {
+        let _ = writeln!(stderr, "kupynasum: write error: {}", sum::describe(e));
+    }
+    status(result)
+}
+
+fn status(result: io::Result<bool>) -> i32 {
+    match result {
+        Ok(true) => 0,
+        _ => 1,
+    }
+}
@@ -0,0 +1,369 @@
+//! Manifests of directory trees: one digest line per file, in sorted path order.
+//!
+//! A manifest lists every regular file under a directory, with `/`-separated paths relative to
+//! it, in the `<hex>  <path>` format `--check` reads. Paths are sorted by their bytes, so the
+//! same tree always gives the same manifest, whatever order the file system lists it in. The
+//! root digest is the Kupyna hash of the manifest itself, and fingerprints the whole tree.
+//! Symbolic links to files are hashed as the files they point to; links to directories are not
+//! followed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use kupyna::{kupyna_hash, kupyna_hash_file};
+
+use crate::cache::{self, cached, Cache};
+use crate::check::parse_line;
+use crate::encoding::to_hex;
+use crate::options::{parse_jobs, parse_length, Arg, Command, Options, Parser, DEFAULT_LENGTH};
+use crate::pool::map_ordered;
+use crate::sum::{describe, escape_name, escape_os_name, format_line};
+
+pub(crate) const USAGE: &str = "\
+Usage: kupynasum manifest [OPTION]... DIR
+Print a manifest of the Kupyna digests of every file under DIR, in sorted path order.
+
+      --cache=FILE         reuse the digests of unchanged files recorded in FILE,
+                             and record new ones; the default is $KUPYNASUM_CACHE,
+                             and --verify never uses one
+  -j, --jobs=N             hash up to N files at once, or one per CPU if N is 0
+  -l, --length=BITS        digest length in bits, a multiple of 8 from 8 to 512;
+                             the default is 256
+      --no-cache           hash every file, even with --cache or $KUPYNASUM_CACHE
+  -r, --root               print only the root digest, the hash of the manifest
+      --verify=MANIFEST    compare DIR with MANIFEST, listing every file added,
+                             removed or modified since; exit non-zero if any was
+  -h, --help               display this help and exit
+";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ManifestOptions {
+    /// The digest length in bits, if given.
+    pub(crate) length: Option<usize>,
+    pub(crate) root: bool,
+    /// The digest cache file, if given.
+    pub(crate) cache: Option<PathBuf>,
+    pub(crate) no_cache: bool,
+    /// The most files to hash at once.
+    pub(crate) jobs: usize,
+    /// The manifest to verify the directory against.
+    pub(crate) verify: Option<PathBuf>,
+    pub(crate) dir: PathBuf,
+}
+
+const SHORT: &[(char, &str)] = &[('j', "jobs"), ('l', "length"), ('r', "root"), ('h', "help")];
+
+/// Parses the arguments after `manifest`.
+pub(crate) fn parse<I: Iterator<Item = OsString>>(args: I) -> Result<Command, String> {
+    let mut options = ManifestOptions::default();
+    let mut dirs = Vec::new();
+    let mut parser = Parser::new(args, SHORT);
+    while let Some(arg) = parser.next()? {
+        let name = match arg {
+            Arg::Operand(dir) => {
+                dirs.push(dir);
+                continue;
+            }
+            Arg::Option(name) => name,
+        };
+        match name.as_str() {
+            "length" => options.length = Some(parse_length(&parser.text_value()?)?),
+            "root" => options.root = true,
+            "cache" => options.cache = Some(parser.value()?.into()),
+            "no-cache" => options.no_cache = true,
+            "jobs" => options.jobs = parse_jobs(&parser.text_value()?)?,
+            "verify" => options.verify = Some(parser.value()?.into()),
+            "help" => return Ok(Command::Help(USAGE)),
+            _ => return Err(parser.unknown()),
+        }
+    }
+
+    options.dir = match <[OsString; 1]>::try_from(dirs) {
+        Ok([dir]) => dir.into(),
+        Err(dirs) if dirs.is_empty() => return Err("missing directory operand".into()),
+        Err(dirs) => return Err(format!("extra operand '{}'", dirs[1].to_string_lossy())),
+    };
+    if options.root && options.verify.is_some() {
+        return Err("--root and --verify are mutually exclusive".into());
+    }
+    if options.cache.is_some() && options.verify.is_some() {
+        return Err("--cache and --verify are mutually exclusive".into());
+    }
+    Ok(Command::Manifest(options))
+}
+
+/// Lists the regular files under `root`.
+///
+/// # Arguments
+///
+/// * `root` - The directory to walk.
+///
+/// # Returns
+///
+/// * The `/`-separated paths relative to `root`, sorted by their bytes, or a message naming
+///   the first entry that could not be read.
+pub(crate) fn walk(root: &Path) -> Result<Vec<String>, String> {
+    let error = |path: &Path, e: io::Error| format!("{}: {}", path.display(), describe(&e));
+    let mut files = Vec::new();
+    let mut dirs = vec![String::new()];
+    while let Some(dir) = dirs.pop() {
+        let path = root.join(&dir);
+        for entry in fs::read_dir(&path).map_err(|e| error(&path, e))? {
+            let entry = entry.map_err(|e| error(&path, e))?;
+            let name = entry.file_name().into_string().map_err(|name| {
+                let path = path.join(name);
+                format!("{}: file name is not valid UTF-8", path.display())
+            })?;
+            let relative = if dir.is_empty() {
+                name
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            let file_type = entry.file_type().map_err(|e| error(&entry.path(), e))?;
+            if file_type.is_dir() {
+                dirs.push(relative);
+            } else if file_type.is_file()
+                || (file_type.is_symlink() && entry.path().metadata().is_ok_and(|m| m.is_file()))
+            {
+                files.push(relative);
+            }
+        }
+    }
+    files.sort_unstable();
+    Ok(files)
+}
+
+/// Builds the manifest of a directory.
+///
+/// # Arguments
+///
+/// * `root` - The directory.
+/// * `n` - The length of the file digests in bits.
+/// * `jobs` - The most files to hash at once.
+/// * `cache` - The digest cache, if any.
+///
+/// # Returns
+///
+/// * The manifest text, or a message naming the first file that could not be read.
+pub(crate) fn build(
+    root: &Path,
+    n: usize,
+    jobs: usize,
+    cache: Option<&Cache>,
+) -> Result<String, String> {
+    let mut manifest = String::new();
+    for (path, digest) in hash_tree(root, n, jobs, cache)? {
+        // Paths under the root are UTF-8, as `walk` gives them, so the line is too
+        let line = format_line(&Options::default(), &digest, path.as_ref());
+        manifest.push_str(&String::from_utf8_lossy(&line));
+        manifest.push('\n');
+    }
+    Ok(manifest)
+}
+
+/// Hashes every regular file under a directory.
+///
+/// # Arguments
+///
+/// * `root` - The directory.
+/// * `n` - The length of the file digests in bits.
+/// * `jobs` - The most files to hash at once.
+/// * `cache` - The digest cache, if any.
+///
+/// # Returns
+///
+/// * The relative paths with their digests, in the order of `walk`, or a message naming the
+///   first file that could not be read.
+pub(crate) fn hash_tree(
+    root: &Path,
+    n: usize,
+    jobs: usize,
+    cache: Option<&Cache>,
+) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut hashed = Vec::new();
+    let work = |path: String| {
+        let digest = hash_path(&root.join(&path), n, cache);
+        (path, digest)
+    };
+    map_ordered(walk(root)?, jobs, work, |(path, digest)| {
+        hashed.push((path, digest?));
+        Ok::<_, String>(())
+    })?;
+    Ok(hashed)
+}
+
+/// Hashes one file, through `cache` if there is one, with a message naming it if it cannot be
+/// read.
+pub(crate) fn hash_path(path: &Path, n: usize, cache: Option<&Cache>) -> Result<Vec<u8>, String> {
+    cached(cache, path, n, |path| kupyna_hash_file(path, n))
+        .map_err(|e| format!("{}: {}", path.display(), describe(&e)))
+}
+
+/// The root digest: the hash of the manifest text.
+pub(crate) fn root_digest(manifest: &str, n: usize) -> Vec<u8> {
+    kupyna_hash(manifest.as_bytes(), n)
+}
+
+/// How a file differs between a tree and its manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// Reads the entries of a manifest.
+///
+/// # Arguments
+///
+/// * `manifest` - The manifest text.
+/// * `length` - The only digest length in bits to accept, or `None` to take it from each line.
+///
+/// # Returns
+///
+/// * The digest listed for each path, or a message naming the first bad line.
+pub(crate) fn parse_manifest(
+    manifest: &str,
+    length: Option<usize>,
+) -> Result<BTreeMap<String, Vec<u8>>, String> {
+    let mut entries = BTreeMap::new();
+    for (index, line) in manifest.lines().enumerate() {
+        if line.starts_with('#') {
+            continue;
+        }
+        // Manifests list paths as text, which their lines were read as
+        let (name, digest) = parse_line(line.as_bytes(), length)
+            .and_then(|entry| Some((entry.name.into_string().ok()?, entry.digest)))
+            .ok_or_else(|| format!("{}: improperly formatted manifest line", index + 1))?;
+        if entries.insert(name, digest).is_some() {
+            return Err(format!("{}: duplicate manifest entry", index + 1));
+        }
+    }
+    Ok(entries)
+}
+
+/// Compares a directory with the entries of a manifest of it.
+///
+/// # Arguments
+///
+/// * `expected` - The manifest entries.
+/// * `root` - The directory.
+/// * `jobs` - The most files to hash at once.
+///
+/// # Returns
+///
+/// * Every changed file with how it changed, in path order, or a message naming a file that
+///   could not be read.
+pub(crate) fn verify(
+    expected: &BTreeMap<String, Vec<u8>>,
+    root: &Path,
+    jobs: usize,
+) -> Result<Vec<(String, Change)>, String> {
+    let found: BTreeSet<String> = walk(root)?.into_iter().collect();
+    let (kept, removed): (Vec<_>, Vec<_>) =
+        expected.iter().partition(|(path, _)| found.contains(*path));
+    let mut changes: Vec<_> = removed
+        .into_iter()
+        .map(|(path, _)| (path.clone(), Change::Removed))
+        .collect();
+    let work = |(path, digest): (&String, &Vec<u8>)| {
+        // Verifying never trusts the cache, since a file can change without its metadata
+        // showing it
+        let actual = hash_path(&root.join(path), digest.len() * 8, None);
+        (path.clone(), actual.map(|actual| &actual != digest))
+    };
+    map_ordered(kept, jobs, work, |(path, modified)| {
+        if modified? {
+            changes.push((path, Change::Modified));
+        }
+        Ok::<_, String>(())
+    })?;
+    for path in found {
+        if !expected.contains_key(&path) {
+            changes.push((path, Change::Added));
+        }
+    }
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(changes)
+}
+
+/// Runs the `manifest` subcommand.
+///
+/// # Returns
+///
+/// * Whether it succeeded and, when verifying, found no changes, or the error that stopped
+///   output.
+pub(crate) fn run(
+    options: &ManifestOptions,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> io::Result<bool> {
+    let root = options.dir.as_path();
+
+    if let Some(list) = &options.verify {
+        let from_stdin = list.as_os_str() == "-";
+        let mut contents = Vec::new();
+        let read = if from_stdin {
+            stdin.read_to_end(&mut contents)
+        } else {
+            File::open(list).and_then(|mut file| file.read_to_end(&mut contents))
+        };
+        if let Err(e) = read {
+            writeln!(stderr, "kupynasum: {}: {}", list.display(), describe(&e))?;
+            return Ok(false);
+        }
+        let display = if from_stdin {
+            "standard input".into()
+        } else {
+            list.to_string_lossy()
+        };
+        let expected = match parse_manifest(&String::from_utf8_lossy(&contents), options.length) {
+            Ok(expected) => expected,
+            Err(message) => {
+                writeln!(stderr, "kupynasum: {}: {}", display, message)?;
+                return Ok(false);
+            }
+        };
+        let changes = match verify(&expected, root, options.jobs) {
+            Ok(changes) => changes,
+            Err(message) => {
+                writeln!(stderr, "kupynasum: {}", message)?;
+                return Ok(false);
+            }
+        };
+        for (path, change) in &changes {
+            let (prefix, path) = escape_name(path);
+            let change = match change {
+                Change::Added => "added",
+                Change::Removed => "removed",
+                Change::Modified => "modified",
+            };
+            writeln!(stdout, "{}{}: {}", prefix, change, path)?;
+        }
+        return Ok(changes.is_empty());
+    }
+
+    let n = options.length.unwrap_or(DEFAULT_LENGTH);
+    let cache = cache::open(options.cache.as_deref(), options.no_cache);
+    let built = build(root, n, options.jobs, cache.as_ref());
+    cache::save(cache, stderr)?;
+    let manifest = match built {
+        Ok(manifest) => manifest,
+        Err(message) => {
+            writeln!(stderr, "kupynasum: {}", message)?;
+            return Ok(false);
+        }
+    };
+    if options.root {
+        let (prefix, dir) = escape_os_name(options.dir.as_os_str());
+        let digest = to_hex(&root_digest(&manifest, n));
+        stdout.write_all(&[prefix.as_bytes(), digest.as_bytes(), b"  ", &dir, b"\n"].concat())?;
+    } else {
+        stdout.write_all(manifest.as_bytes())?;
+    }
+    Ok(true)
+}
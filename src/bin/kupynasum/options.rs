@@ -0,0 +1,401 @@
+//! Command-line parsing, in the style of coreutils `sha256sum`.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::diff::{self, DiffOptions};
+use crate::dupes::{self, DupesOptions};
+use crate::encoding::os_string;
+use crate::kdf::{self, KdfKind, KdfOptions};
+use crate::mac::{self, KeySource, MacKind, MacOptions};
+use crate::manifest::{self, ManifestOptions};
+use crate::pool::default_jobs;
+
+pub(crate) const USAGE: &str = "\
+Usage: kupynasum [OPTION]... [FILE]...
+  or:  kupynasum manifest [OPTION]... DIR
+  or:  kupynasum diff [OPTION]... LEFT RIGHT
+  or:  kupynasum dupes [OPTION]... DIR...
+  or:  kupynasum hmac|kmac [OPTION]... [FILE]...
+  or:  kupynasum hkdf|pbkdf2 [OPTION]...
+Print or check Kupyna (DSTU 7564) checksums.
+
+With no FILE, or when FILE is -, read standard input.
+
+  -b, --binary          read in binary mode, marking names with '*'
+      --cache=FILE      reuse the digests of unchanged files recorded in FILE,
+                          and record new ones; the default is $KUPYNASUM_CACHE,
+                          and --check never uses one
+  -c, --check           read checksums from the FILEs and check them
+  -j, --jobs=N          hash up to N files at once, or one per CPU if N is 0;
+                          output stays in the order of the FILEs
+  -l, --length=BITS     digest length in bits, a multiple of 8 from 8 to 512;
+                          the default is 256, and --check detects it per line
+  -t, --text            read in text mode (default)
+      --tag             create a BSD-style checksum, KUPYNA-256 (FILE) = DIGEST
+      --json            create JSON lines with the file, algorithm and digest
+      --base64          print digests in base64 rather than hex
+      --no-cache        hash every file, even with --cache or $KUPYNASUM_CACHE
+  -h, --help            display this help and exit
+  -V, --version         output version information and exit
+
+The following five options are useful only when verifying checksums:
+      --ignore-missing  don't fail or report status for missing files
+      --quiet           don't print OK for each successfully verified file
+      --status          don't output anything, status code shows success
+      --strict          exit non-zero for improperly formatted checksum lines
+  -w, --warn            warn about improperly formatted checksum lines
+
+Subcommands:
+  manifest              hash a directory tree; see 'kupynasum manifest --help'
+  diff                  compare two directory trees; see 'kupynasum diff --help'
+  dupes                 find duplicate files; see 'kupynasum dupes --help'
+  hmac, kmac            print or check keyed codes; see 'kupynasum hmac --help'
+  hkdf, pbkdf2          derive keys from a secret; see 'kupynasum hkdf --help'
+";
+
+pub(crate) const DEFAULT_LENGTH: usize = 256;
+
+/// How each digest line is laid out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// `<digest>  <name>`, as `sha256sum` prints.
+    #[default]
+    Gnu,
+    /// `KUPYNA-<bits> (<name>) = <digest>`, as BSD `sha256` and `sha256sum --tag` print.
+    Tag,
+    /// `{"file":<name>,"algorithm":"KUPYNA-<bits>","digest":<digest>}`.
+    Json,
+}
+
+/// What the command line asks for.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Command {
+    /// Print this usage text.
+    Help(&'static str),
+    Version,
+    Sum(Options),
+    Manifest(ManifestOptions),
+    Diff(DiffOptions),
+    Dupes(DupesOptions),
+    Kdf(KdfOptions),
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Options {
+    /// The digest length in bits, if given.
+    pub(crate) length: Option<usize>,
+    pub(crate) check: bool,
+    pub(crate) binary: bool,
+    /// The digest cache file, if given.
+    pub(crate) cache: Option<PathBuf>,
+    pub(crate) no_cache: bool,
+    /// The most files to hash at once.
+    pub(crate) jobs: usize,
+    pub(crate) format: Format,
+    /// Whether digests are printed in base64 rather than hex.
+    pub(crate) base64: bool,
+    pub(crate) ignore_missing: bool,
+    pub(crate) quiet: bool,
+    pub(crate) status: bool,
+    pub(crate) strict: bool,
+    pub(crate) warn: bool,
+    pub(crate) files: Vec<OsString>,
+    /// The MAC to compute instead of digests, for `hmac` and `kmac`.
+    pub(crate) mac: Option<MacOptions>,
+}
+
+/// Whether `bits` is a Kupyna hash code length.
+pub(crate) fn is_valid_length(bits: usize) -> bool {
+    (8..=512).contains(&bits) && bits.is_multiple_of(8)
+}
+
+/// An argument, as `getopt_long` splits them.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Arg {
+    /// An option, by its long name even when given in short form.
+    Option(String),
+    Operand(OsString),
+}
+
+/// Splits arguments into options and operands the way `getopt_long` does.
+///
+/// Short options can be grouped, as in `-cw`. An option's value can follow it in the same
+/// argument, as in `-l384` and `--length=384`, or be the next argument. `--` ends the options.
+///
+/// Arguments need not be valid UTF-8. Operands and option values keep their bytes, so a file
+/// name is never replaced by a lossy copy that could name another file.
+pub(crate) struct Parser<I> {
+    args: I,
+    /// Short option names and the long names they stand for.
+    short: &'static [(char, &'static str)],
+    /// The rest of a group of short options.
+    group: Option<Vec<u8>>,
+    /// The `=value` of the last long option.
+    value: Option<OsString>,
+    /// How the last option was written, for error messages.
+    last: String,
+    operands_only: bool,
+}
+
+impl<I: Iterator<Item = OsString>> Parser<I> {
+    pub(crate) fn new<A: IntoIterator<IntoIter = I>>(
+        args: A,
+        short: &'static [(char, &'static str)],
+    ) -> Self {
+        Parser {
+            args: args.into_iter(),
+            short,
+            group: None,
+            value: None,
+            last: String::new(),
+            operands_only: false,
+        }
+    }
+
+    /// Returns the next argument, or an error for an unknown short option or a long option
+    /// given a value it did not take.
+    pub(crate) fn next(&mut self) -> Result<Option<Arg>, String> {
+        if self.value.take().is_some() {
+            return Err(format!("option '{}' doesn't allow an argument", self.last));
+        }
+        if let Some(group) = self.group.take() {
+            // Short options are ASCII, so anything else is not one
+            let flag = match group[0] {
+                flag if flag.is_ascii() => flag as char,
+                _ => {
+                    let text = String::from_utf8_lossy(&group);
+                    let flag = text
+                        .chars()
+                        .next()
+                        .expect("short option groups are never empty");
+                    return Err(format!("invalid option -- '{}'", flag));
+                }
+            };
+            if group.len() > 1 {
+                self.group = Some(group[1..].to_vec());
+            }
+            self.last = format!("-{}", flag);
+            return match self.short.iter().find(|(short, _)| *short == flag) {
+                Some((_, long)) => Ok(Some(Arg::Option(long.to_string()))),
+                None => Err(format!("invalid option -- '{}'", flag)),
+            };
+        }
+
+        let Some(arg) = self.args.next() else {
+            return Ok(None);
+        };
+        let bytes = arg.as_encoded_bytes();
+        if self.operands_only || arg == "-" || !bytes.starts_with(b"-") {
+            return Ok(Some(Arg::Operand(arg)));
+        }
+        if arg == "--" {
+            self.operands_only = true;
+            return self.next();
+        }
+        if let Some(long) = bytes.strip_prefix(b"--") {
+            let name = match long.iter().position(|&b| b == b'=') {
+                Some(equals) => {
+                    self.value = Some(os_string(long[equals + 1..].to_vec()));
+                    &long[..equals]
+                }
+                None => long,
+            };
+            // Long names are ASCII, so one that is not UTF-8 is unknown whatever it becomes
+            let name = String::from_utf8_lossy(name).into_owned();
+            self.last = format!("--{}", name);
+            return Ok(Some(Arg::Option(name)));
+        }
+        self.group = Some(bytes[1..].to_vec());
+        self.next()
+    }
+
+    /// Takes the value of the option just returned: the rest of its argument, or the next one.
+    pub(crate) fn value(&mut self) -> Result<OsString, String> {
+        if let Some(value) = self.value.take() {
+            return Ok(value);
+        }
+        if let Some(group) = self.group.take() {
+            return Ok(os_string(group));
+        }
+        self.args
+            .next()
+            .ok_or_else(|| match self.last.strip_prefix("--") {
+                Some(_) => format!("option '{}' requires an argument", self.last),
+                None => format!("option requires an argument -- '{}'", &self.last[1..]),
+            })
+    }
+
+    /// Takes the value of the option just returned, as `value` does, for an option whose value
+    /// is text rather than a name.
+    pub(crate) fn text_value(&mut self) -> Result<String, String> {
+        self.value()?.into_string().map_err(|value| {
+            format!(
+                "invalid argument '{}' for '{}'",
+                value.to_string_lossy(),
+                self.last
+            )
+        })
+    }
+
+    /// The error for an option the caller does not know.
+    pub(crate) fn unknown(&self) -> String {
+        format!("unrecognized option '{}'", self.last)
+    }
+}
+
+const SUBCOMMANDS: &[&str] = &[
+    "manifest", "diff", "dupes", "hmac", "kmac", "hkdf", "pbkdf2",
+];
+
+const SHORT: &[(char, &str)] = &[
+    ('b', "binary"),
+    ('c', "check"),
+    ('j', "jobs"),
+    ('l', "length"),
+    ('t', "text"),
+    ('w', "warn"),
+    ('h', "help"),
+    ('V', "version"),
+];
+
+/// Parses the arguments after the program name.
+///
+/// A first argument naming a subcommand selects it. Files with such names can still be hashed
+/// by writing them after `--`.
+///
+/// # Arguments
+///
+/// * `args` - The arguments, without the program name.
+///
+/// # Returns
+///
+/// * The `Command` to run, or a message describing the first bad argument.
+pub(crate) fn parse<I: IntoIterator<Item = OsString>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let subcommand = args.next_if(|arg| arg.to_str().is_some_and(|arg| SUBCOMMANDS.contains(&arg)));
+    match subcommand.as_ref().and_then(|arg| arg.to_str()) {
+        Some("manifest") => return manifest::parse(args),
+        Some("diff") => return diff::parse(args),
+        Some("dupes") => return dupes::parse(args),
+        Some("hmac") => return parse_sum(args, Some(MacKind::Hmac)),
+        Some("kmac") => return parse_sum(args, Some(MacKind::Kmac)),
+        Some("hkdf") => return kdf::parse(args, KdfKind::Hkdf),
+        Some("pbkdf2") => return kdf::parse(args, KdfKind::Pbkdf2),
+        _ => {}
+    }
+    parse_sum(args, None)
+}
+
+/// Parses the options of plain hashing, or of `hmac` and `kmac` when there is a `mac`.
+fn parse_sum<I: Iterator<Item = OsString>>(
+    args: I,
+    mac: Option<MacKind>,
+) -> Result<Command, String> {
+    let mut options = Options::default();
+    let mut key = None;
+    let mut hex_key = false;
+    let mut parser = Parser::new(args, SHORT);
+    while let Some(arg) = parser.next()? {
+        let name = match arg {
+            Arg::Operand(file) => {
+                options.files.push(file);
+                continue;
+            }
+            Arg::Option(name) => name,
+        };
+        match name.as_str() {
+            "length" => options.length = Some(parse_length(&parser.text_value()?)?),
+            "jobs" => options.jobs = parse_jobs(&parser.text_value()?)?,
+            "binary" => options.binary = true,
+            "cache" if mac.is_none() => options.cache = Some(parser.value()?.into()),
+            "no-cache" if mac.is_none() => options.no_cache = true,
+            "key-file" if mac.is_some() => {
+                set_key(&mut key, KeySource::File(parser.value()?.into()))?
+            }
+            "key-env" if mac.is_some() => set_key(&mut key, KeySource::Env(parser.text_value()?))?,
+            "hex-key" if mac.is_some() => hex_key = true,
+            "key" if mac.is_some() => {
+                return Err("keys are not taken as arguments; use --key-file or --key-env".into())
+            }
+            "check" => options.check = true,
+            "text" => options.binary = false,
+            "tag" => set_format(&mut options, Format::Tag)?,
+            "json" => set_format(&mut options, Format::Json)?,
+            "base64" => options.base64 = true,
+            "ignore-missing" => options.ignore_missing = true,
+            "quiet" => options.quiet = true,
+            "status" => options.status = true,
+            "strict" => options.strict = true,
+            "warn" => options.warn = true,
+            "help" if mac.is_some() => return Ok(Command::Help(mac::USAGE)),
+            "help" => return Ok(Command::Help(USAGE)),
+            "version" => return Ok(Command::Version),
+            _ => return Err(parser.unknown()),
+        }
+    }
+
+    if let Some(kind) = mac {
+        let key = key.ok_or("a key is required; use --key-file or --key-env")?;
+        options.mac = Some(MacOptions { kind, key, hex_key });
+    }
+    if options.files.is_empty() {
+        options.files.push("-".into());
+    }
+    let verify_only =
+        options.ignore_missing || options.quiet || options.status || options.strict || options.warn;
+    if verify_only && !options.check {
+        return Err("the verification options are meaningful only when verifying checksums".into());
+    }
+    if options.check {
+        // Checking reads every layout and encoding without being told, and every file
+        let output = [
+            (options.format == Format::Tag, "--tag"),
+            (options.format == Format::Json, "--json"),
+            (options.base64, "--base64"),
+            (options.cache.is_some(), "--cache"),
+        ];
+        if let Some((_, option)) = output.iter().find(|(given, _)| *given) {
+            return Err(format!(
+                "the {} option is meaningless when verifying checksums",
+                option
+            ));
+        }
+    }
+    Ok(Command::Sum(options))
+}
+
+fn set_key(key: &mut Option<KeySource>, source: KeySource) -> Result<(), String> {
+    if key.is_some() {
+        return Err("only one of --key-file and --key-env can give the key".into());
+    }
+    *key = Some(source);
+    Ok(())
+}
+
+fn set_format(options: &mut Options, format: Format) -> Result<(), String> {
+    if options.format != Format::Gnu && options.format != format {
+        return Err("--tag and --json are mutually exclusive".into());
+    }
+    options.format = format;
+    Ok(())
+}
+
+/// Parses a `--jobs` value, where 0 means one job per CPU.
+pub(crate) fn parse_jobs(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) => Ok(default_jobs()),
+        Ok(jobs) => Ok(jobs),
+        Err(_) => Err(format!("invalid number of jobs: '{}'", value)),
+    }
+}
+
+pub(crate) fn parse_length(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(bits) if is_valid_length(bits) => Ok(bits),
+        _ => Err(format!(
+            "invalid length: '{}'; it must be a multiple of 8 from 8 to 512",
+            value
+        )),
+    }
+}
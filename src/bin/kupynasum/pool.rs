@@ -0,0 +1,79 @@
+//! A pool of worker threads whose results come out in input order.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// Applies `work` to every item on up to `jobs` threads, passing the results to `emit` in the
+/// order of `items`.
+///
+/// Each result is emitted as soon as it and every result before it are ready, so output starts
+/// before the slowest item finishes. With one job, or one item, everything runs on the calling
+/// thread.
+///
+/// # Arguments
+///
+/// * `items` - The inputs.
+/// * `jobs` - The most threads to use.
+/// * `work` - The function run on each item, on a worker thread.
+/// * `emit` - Receives each result, on the calling thread.
+///
+/// # Returns
+///
+/// * The first error `emit` returns, after which no more items are started.
+pub(crate) fn map_ordered<T, R, E>(
+    items: Vec<T>,
+    jobs: usize,
+    work: impl Fn(T) -> R + Sync,
+    mut emit: impl FnMut(R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Send,
+    R: Send,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        return items.into_iter().try_for_each(|item| emit(work(item)));
+    }
+
+    let workers = jobs.min(items.len());
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let (sender, queue, stop, work) = (sender.clone(), &queue, &stop, &work);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let Some((index, item)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    if sender.send((index, work(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Results that finished before some earlier one, by index
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&next) {
+                next += 1;
+                if let Err(e) = emit(result) {
+                    stop.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The number of jobs to use for `--jobs 0`: one per CPU.
+pub(crate) fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
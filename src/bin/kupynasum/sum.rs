@@ -0,0 +1,305 @@
+//! Hashing files and printing checksum lines.
+
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+use kupyna::{kupyna_hash_file, KupynaHasher};
+
+use crate::cache::{self, cached, Cache};
+use crate::encoding::{json_string, to_base64, to_hex};
+use crate::mac::Mac;
+use crate::options::{Format, Options, DEFAULT_LENGTH};
+use crate::pool::map_ordered;
+
+/// How much of a file is read at a time.
+const READ_SIZE: usize = 64 * 1024;
+
+/// Hashes everything `reader` yields.
+///
+/// # Arguments
+///
+/// * `reader` - The data to hash.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * The hash code, or the first read error.
+pub(crate) fn hash_reader<R: Read>(reader: R, n: usize) -> io::Result<Vec<u8>> {
+    let mut hasher = KupynaHasher::new(n);
+    read_chunks(reader, |chunk| hasher.update(chunk))?;
+    Ok(hasher.finalize())
+}
+
+/// Passes everything `reader` yields to `consume`, a chunk at a time.
+///
+/// # Returns
+///
+/// * The first read error.
+pub(crate) fn read_chunks<R: Read>(
+    mut reader: R,
+    mut consume: impl FnMut(&[u8]),
+) -> io::Result<()> {
+    let mut buffer = vec![0; READ_SIZE];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => consume(&buffer[..read]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Hashes a named file, with `-` meaning standard input, through `cache` if there is one, or
+/// computes its code if there is a `mac`.
+///
+/// Files are read on a second thread while this one compresses, so one large file hashes in
+/// about the time of the slower of the two.
+pub(crate) fn hash_file(
+    name: &OsStr,
+    n: usize,
+    stdin: &mut dyn Read,
+    cache: Option<&Cache>,
+    mac: Option<&Mac>,
+) -> io::Result<Vec<u8>> {
+    match (name == "-", mac) {
+        (true, Some(mac)) => mac.code_reader(stdin, n),
+        (true, None) => hash_reader(stdin, n),
+        (false, Some(mac)) => File::open(name).and_then(|file| mac.code_reader(file, n)),
+        (false, None) => cached(cache, Path::new(name), n, |path| kupyna_hash_file(path, n)),
+    }
+}
+
+/// Hashes named files, with `-` meaning standard input, on up to `jobs` threads.
+///
+/// Standard input cannot be shared with worker threads, so with more than one job every `-` is
+/// streamed on the calling thread, in turn, while the workers hash the other files. Only the
+/// first `-` gets what was there, as when hashing one file at a time. Results are still emitted
+/// in the order of `files`.
+///
+/// # Arguments
+///
+/// * `files` - The names with the hash code length in bits for each, or `None` for a gap.
+/// * `jobs` - The most files to hash at once.
+/// * `stdin` - Standard input.
+/// * `cache` - The digest cache, if any.
+/// * `mac` - The MAC to compute instead of digests, if any.
+/// * `emit` - Receives each index into `files` and its digest, in order, with `None` for gaps.
+///
+/// # Returns
+///
+/// * The first error `emit` returns.
+pub(crate) fn hash_files<E>(
+    files: Vec<Option<(&OsStr, usize)>>,
+    jobs: usize,
+    stdin: &mut dyn Read,
+    cache: Option<&Cache>,
+    mac: Option<&Mac>,
+    mut emit: impl FnMut(usize, Option<io::Result<Vec<u8>>>) -> Result<(), E>,
+) -> Result<(), E> {
+    if jobs <= 1 {
+        for (index, file) in files.into_iter().enumerate() {
+            emit(
+                index,
+                file.map(|(name, n)| hash_file(name, n, stdin, cache, mac)),
+            )?;
+        }
+        return Ok(());
+    }
+
+    let named: Vec<(&OsStr, usize)> = files
+        .iter()
+        .flatten()
+        .filter(|(name, _)| *name != "-")
+        .copied()
+        .collect();
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        scope.spawn(move || {
+            let work = |(name, n)| hash_file(name, n, &mut io::empty(), cache, mac);
+            // Sending fails once the calling thread stops emitting, which stops the workers
+            map_ordered(named, jobs, work, |digest| sender.send(digest))
+        });
+
+        let mut from_stdin: VecDeque<_> = files
+            .iter()
+            .flatten()
+            .filter(|(name, _)| *name == "-")
+            .map(|&(name, n)| hash_file(name, n, stdin, None, mac))
+            .collect();
+        for (index, file) in files.into_iter().enumerate() {
+            let digest = file.map(|(name, _)| {
+                let digest = if name == "-" {
+                    from_stdin.pop_front()
+                } else {
+                    receiver.recv().ok()
+                };
+                digest.expect("a digest for every file")
+            });
+            emit(index, digest)?;
+        }
+        Ok(())
+    })
+}
+
+/// Formats a checksum line in the layout and encoding `options` ask for.
+///
+/// Outside JSON, names containing a backslash or a newline are escaped, and the line then
+/// starts with a backslash so `--check` knows to undo it. Other bytes of the name are kept as
+/// they are, even where they are not UTF-8, as `sha256sum` keeps them. JSON strings cannot hold
+/// such names, so `sum` refuses them before formatting a JSON line.
+///
+/// # Arguments
+///
+/// * `options` - The output options.
+/// * `digest` - The hash code.
+/// * `name` - The file name.
+///
+/// # Returns
+///
+/// * The line, without its newline.
+pub(crate) fn format_line(options: &Options, digest: &[u8], name: &OsStr) -> Vec<u8> {
+    let encoded = if options.base64 {
+        to_base64(digest)
+    } else {
+        to_hex(digest)
+    };
+    let algorithm = format!("{}KUPYNA-{}", algorithm_prefix(options), digest.len() * 8);
+    match options.format {
+        Format::Gnu => {
+            let (prefix, name) = escape_os_name(name);
+            let marker = if options.binary { " *" } else { "  " };
+            [
+                prefix.as_bytes(),
+                encoded.as_bytes(),
+                marker.as_bytes(),
+                &name,
+            ]
+            .concat()
+        }
+        Format::Tag => {
+            let (prefix, name) = escape_os_name(name);
+            let (open, close) = (
+                format!("{}{} (", prefix, algorithm),
+                format!(") = {}", encoded),
+            );
+            [open.as_bytes(), &name, close.as_bytes()].concat()
+        }
+        Format::Json => format!(
+            "{{\"file\":{},\"algorithm\":{},\"digest\":{}}}",
+            json_string(&name.to_string_lossy()),
+            json_string(&algorithm),
+            json_string(&encoded)
+        )
+        .into_bytes(),
+    }
+}
+
+/// The prefix of the algorithm name in tagged and JSON lines: `HMAC-` or `KMAC-` for codes.
+pub(crate) fn algorithm_prefix(options: &Options) -> &'static str {
+    options.mac.as_ref().map_or("", |mac| mac.kind.prefix())
+}
+
+/// Escapes a file name for output, returning the line prefix it needs along with it.
+pub(crate) fn escape_name(name: &str) -> (&'static str, String) {
+    if !name.contains(['\\', '\n']) {
+        return ("", name.to_string());
+    }
+    let escaped = name.replace('\\', "\\\\").replace('\n', "\\n");
+    ("\\", escaped)
+}
+
+/// Escapes a file name for output as `escape_name` does, keeping its other bytes as they are.
+pub(crate) fn escape_os_name(name: &OsStr) -> (&'static str, Vec<u8>) {
+    let name = name.as_encoded_bytes();
+    if !name.contains(&b'\\') && !name.contains(&b'\n') {
+        return ("", name.to_vec());
+    }
+    let mut escaped = Vec::with_capacity(name.len() + 1);
+    for &b in name {
+        match b {
+            b'\\' => escaped.extend_from_slice(b"\\\\"),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            b => escaped.push(b),
+        }
+    }
+    ("\\", escaped)
+}
+
+/// Describes an I/O error the way coreutils does, without Rust's `(os error N)` suffix.
+pub(crate) fn describe(error: &io::Error) -> String {
+    let message = error.to_string();
+    match message.find(" (os error ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
+
+/// Prints a checksum line, or a code line with a `mac`, for every file in `options`.
+///
+/// # Returns
+///
+/// * Whether every file could be read, or the error that stopped output.
+pub(crate) fn sum(
+    options: &Options,
+    mac: Option<&Mac>,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> io::Result<bool> {
+    let n = options
+        .length
+        .unwrap_or(mac.map_or(DEFAULT_LENGTH, Mac::default_length));
+    if let Some(Err(message)) = mac.map(|mac| mac.check_length(n)) {
+        writeln!(stderr, "kupynasum: {}", message)?;
+        return Ok(false);
+    }
+    let files = options
+        .files
+        .iter()
+        .map(|name| Some((name.as_os_str(), n)))
+        .collect();
+    let cache = cache::open(options.cache.as_deref(), options.no_cache);
+    let mut ok = true;
+    hash_files(
+        files,
+        options.jobs,
+        stdin,
+        cache.as_ref(),
+        mac,
+        |index, digest| {
+            let name = &options.files[index];
+            match digest.expect("every name is hashed") {
+                Ok(_) if options.format == Format::Json && name.to_str().is_none() => {
+                    ok = false;
+                    writeln!(
+                        stderr,
+                        "kupynasum: {}: file name is not valid UTF-8, which JSON cannot hold",
+                        Path::new(name).display()
+                    )
+                }
+                Ok(digest) => {
+                    let mut line = format_line(options, &digest, name);
+                    line.push(b'\n');
+                    stdout.write_all(&line)
+                }
+                Err(e) => {
+                    ok = false;
+                    writeln!(
+                        stderr,
+                        "kupynasum: {}: {}",
+                        Path::new(name).display(),
+                        describe(&e)
+                    )
+                }
+            }
+        },
+    )?;
+    cache::save(cache, stderr)?;
+    Ok(ok)
+}
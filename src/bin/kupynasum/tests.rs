@@ -0,0 +1,1389 @@
+use super::*;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use kupyna::{hkdf_kupyna, hmac_kupyna, kmac_kupyna, kupyna_hash, pbkdf2_kupyna};
+
+use crate::check::{parse_line, Entry};
+use crate::diff::{compare, Difference};
+use crate::dupes::{find_duplicates, link, DupesOptions, DuplicateSet, PREFIX_LEN};
+use crate::encoding::{json_string, parse_base64, parse_hex, to_base64, to_hex};
+use crate::manifest::{build, parse_manifest, root_digest, verify, Change};
+use crate::options::{Arg, Format, Options};
+use crate::pool::map_ordered;
+use crate::sum::format_line;
+
+/// A directory under the system temporary directory, removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "kupynasum-{}-{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    /// Writes a file and returns its path as a string.
+    fn file(&self, name: &str, contents: &[u8]) -> String {
+        let path = self.0.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the command line, returning the exit status, stdout and stderr.
+fn kupynasum(args: &[&str], stdin: &[u8]) -> (i32, String, String) {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let code = run(
+        args.iter().map(OsString::from),
+        &mut &stdin[..],
+        &mut stdout,
+        &mut stderr,
+    );
+    (
+        code,
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_parse_options() {
+    let parsed = options::parse(
+        ["-bl384", "--check", "--length=512", "--warn", "--", "-c"].map(OsString::from),
+    );
+    assert_eq!(
+        parsed,
+        Ok(Command::Sum(Options {
+            length: Some(512),
+            check: true,
+            binary: true,
+            warn: true,
+            files: vec!["-c".into()],
+            ..Options::default()
+        }))
+    );
+
+    let parsed = options::parse(["-l", "128", "a", "-"].map(OsString::from));
+    assert!(
+        matches!(parsed, Ok(Command::Sum(o)) if o.length == Some(128) && o.files == ["a", "-"])
+    );
+    assert!(matches!(
+        options::parse(Vec::new()),
+        Ok(Command::Sum(o)) if o.files == ["-"]
+    ));
+    assert_eq!(
+        options::parse([OsString::from("-h")]),
+        Ok(Command::Help(options::USAGE))
+    );
+
+    for bad in [
+        &["-l", "250"][..],
+        &["--length=0"],
+        &["--length=520"],
+        &["-l"],
+        &["-x"],
+        &["--bogus"],
+        &["--check=yes"],
+        &["--strict"],
+    ] {
+        assert!(
+            options::parse(bad.iter().map(OsString::from)).is_err(),
+            "{:?}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn test_sum_files_and_stdin() {
+    let dir = TempDir::new();
+    let a = dir.file("a", b"hello world");
+    let b = dir.file("b", b"");
+
+    let (code, stdout, stderr) = kupynasum(&[&a, "-", &b], b"from stdin");
+    assert_eq!(code, 0);
+    assert_eq!(stderr, "");
+    assert_eq!(
+        stdout,
+        format!(
+            "{}  {}\n{}  -\n{}  {}\n",
+            to_hex(&kupyna_hash(b"hello world", 256)),
+            a,
+            to_hex(&kupyna_hash(b"from stdin", 256)),
+            to_hex(&kupyna_hash(b"", 256)),
+            b
+        )
+    );
+
+    let (code, stdout, _) = kupynasum(&["-b", "--length", "384"], b"abc");
+    assert_eq!(code, 0);
+    assert_eq!(
+        stdout,
+        format!("{} *-\n", to_hex(&kupyna_hash(b"abc", 384)))
+    );
+}
+
+#[test]
+fn test_sum_large_file() {
+    // Several reads, ending part-way through a block
+    let data: Vec<u8> = (0..200_003u32).map(|i| (i * 7) as u8).collect();
+    let (code, stdout, _) = kupynasum(&["-l", "512"], &data);
+    assert_eq!(code, 0);
+    assert_eq!(stdout, format!("{}  -\n", to_hex(&kupyna_hash(&data, 512))));
+}
+
+#[test]
+fn test_sum_missing_file_continues() {
+    let dir = TempDir::new();
+    let a = dir.file("a", b"a");
+    let missing = dir.0.join("missing").to_str().unwrap().to_string();
+
+    let (code, stdout, stderr) = kupynasum(&[&missing, &a], b"");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stderr,
+        format!("kupynasum: {}: No such file or directory\n", missing)
+    );
+    assert!(stdout.ends_with(&format!("  {}\n", a)));
+}
+
+#[test]
+fn test_escaped_names() {
+    let digest = [0xab, 0xcd];
+    let line = format_line(&Options::default(), &digest, "new\nline\\".as_ref());
+    assert_eq!(line, b"\\abcd  new\\nline\\\\");
+    assert_eq!(
+        parse_line(&line, None),
+        Some(Entry {
+            digest: digest.to_vec(),
+            name: "new\nline\\".into()
+        })
+    );
+    let binary = Options {
+        binary: true,
+        ..Options::default()
+    };
+    assert_eq!(
+        format_line(&binary, &digest, "plain".as_ref()),
+        b"abcd *plain"
+    );
+}
+
+#[test]
+fn test_parse_line() {
+    let hex = "00".repeat(32);
+    assert!(parse_line(format!("{}  name with spaces", hex).as_bytes(), None).is_some());
+    assert!(parse_line(format!("{} *name", hex).as_bytes(), Some(256)).is_some());
+    assert!(parse_line(format!("{}  name", hex.to_uppercase()).as_bytes(), None).is_some());
+
+    // Wrong length for --length, bad separators, bad hex, and no name
+    assert!(parse_line(format!("{}  name", hex).as_bytes(), Some(512)).is_none());
+    assert!(parse_line(format!("{}name", hex).as_bytes(), None).is_none());
+    assert!(parse_line(format!("{} name", hex).as_bytes(), None).is_none());
+    assert!(parse_line(format!("{}  ", hex).as_bytes(), None).is_none());
+    assert!(parse_line(b"0g  name", None).is_none());
+    assert!(parse_line(b"abc  name", None).is_none());
+    assert!(parse_line(format!("{}  name", "00".repeat(65)).as_bytes(), None).is_none());
+}
+
+#[test]
+fn test_check_round_trip() {
+    let dir = TempDir::new();
+    let a = dir.file("a", b"first");
+    let b = dir.file("b", b"second");
+
+    // Lists of mixed lengths check without --length
+    let (_, short, _) = kupynasum(&["-l", "8", &a], b"");
+    let (_, long, _) = kupynasum(&["-l", "512", &b], b"");
+    let list = dir.file("list", format!("{}{}", short, long).as_bytes());
+
+    let (code, stdout, stderr) = kupynasum(&["-c", &list], b"");
+    assert_eq!(code, 0);
+    assert_eq!(stdout, format!("{}: OK\n{}: OK\n", a, b));
+    assert_eq!(stderr, "");
+
+    let (code, stdout, _) = kupynasum(&["--check", "--quiet", "-"], long.as_bytes());
+    assert_eq!((code, stdout.as_str()), (0, ""));
+}
+
+#[test]
+fn test_check_failures() {
+    let dir = TempDir::new();
+    let a = dir.file("a", b"first");
+    let b = dir.file("b", b"second");
+    let missing = dir.0.join("missing").to_str().unwrap().to_string();
+
+    let (_, sums, _) = kupynasum(&[&a, &b], b"");
+    let wrong = to_hex(&kupyna_hash(b"other", 256));
+    let list = format!(
+        "{}{}  {}\n# a comment\nnot a checksum\n{}  {}\n",
+        sums, wrong, a, wrong, missing
+    );
+    let list = dir.file("list", list.as_bytes());
+
+    let (code, stdout, stderr) = kupynasum(&["-c", &list], b"");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stdout,
+        format!(
+            "{a}: OK\n{b}: OK\n{a}: FAILED\n{m}: FAILED open or read\n",
+            a = a,
+            b = b,
+            m = missing
+        )
+    );
+    assert_eq!(
+        stderr,
+        format!(
+            "kupynasum: {}: No such file or directory\n\
+             kupynasum: WARNING: 1 line is improperly formatted\n\
+             kupynasum: WARNING: 1 listed file could not be read\n\
+             kupynasum: WARNING: 1 computed checksum did NOT match\n",
+            missing
+        )
+    );
+
+    let (code, stdout, stderr) = kupynasum(&["-c", "--status", &list], b"");
+    assert_eq!(code, 1);
+    assert_eq!(stdout, "");
+    assert_eq!(
+        stderr,
+        format!("kupynasum: {}: No such file or directory\n", missing)
+    );
+}
+
+#[test]
+fn test_check_format_options() {
+    let dir = TempDir::new();
+    let a = dir.file("a", b"first");
+    let missing = dir.0.join("missing").to_str().unwrap().to_string();
+    let (_, sum, _) = kupynasum(&[&a], b"");
+    let list = dir.file("list", format!("{}bad line\n", sum).as_bytes());
+
+    // Improperly formatted lines only fail with --strict
+    assert_eq!(kupynasum(&["-c", &list], b"").0, 0);
+    assert_eq!(kupynasum(&["-c", "--strict", &list], b"").0, 1);
+    let (_, _, stderr) = kupynasum(&["-cw", &list], b"");
+    assert!(stderr.starts_with(&format!(
+        "kupynasum: {}: 2: improperly formatted KUPYNA checksum line\n",
+        list
+    )));
+
+    // --length rejects lines of other lengths
+    let (code, _, stderr) = kupynasum(&["-c", "-l", "512", &list], b"");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stderr,
+        format!(
+            "kupynasum: {}: no properly formatted checksum lines found\n",
+            list
+        )
+    );
+
+    // Missing files are skipped with --ignore-missing, unless nothing was verified
+    let with_missing = format!("{}{}  {}\n", sum, to_hex(&[0; 32]), missing);
+    let list = dir.file("with_missing", with_missing.as_bytes());
+    let (code, stdout, _) = kupynasum(&["-c", "--ignore-missing", &list], b"");
+    assert_eq!((code, stdout), (0, format!("{}: OK\n", a)));
+    let list = dir.file(
+        "only_missing",
+        format!("{}  {}\n", to_hex(&[0; 32]), missing).as_bytes(),
+    );
+    let (code, _, stderr) = kupynasum(&["-c", "--ignore-missing", &list], b"");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stderr,
+        format!("kupynasum: {}: no file was verified\n", list)
+    );
+
+    let (code, _, stderr) = kupynasum(&["-c", &missing], b"");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stderr,
+        format!("kupynasum: {}: No such file or directory\n", missing)
+    );
+}
+
+#[test]
+fn test_usage_errors() {
+    let (code, stdout, stderr) = kupynasum(&["--length", "7"], b"");
+    assert_eq!((code, stdout.as_str()), (1, ""));
+    assert!(stderr.ends_with("Try 'kupynasum --help' for more information.\n"));
+
+    let (code, stdout, _) = kupynasum(&["--help"], b"");
+    assert_eq!((code, stdout.as_str()), (0, options::USAGE));
+}
+
+#[test]
+fn test_encodings() {
+    // RFC 4648, section 10
+    let vectors = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+    for (plain, encoded) in vectors {
+        assert_eq!(to_base64(plain.as_bytes()), encoded);
+        if !plain.is_empty() {
+            assert_eq!(parse_base64(encoded).unwrap(), plain.as_bytes());
+        }
+    }
+    for bad in [
+        "", "Zg=", "Zg=a", "Zh==", "Z===", "Zg==Zg==", "Zm9v!A==", "Zm8=\n",
+    ] {
+        assert_eq!(parse_base64(bad), None, "{:?}", bad);
+    }
+
+    assert_eq!(parse_hex("00aBfF"), Some(vec![0x00, 0xab, 0xff]));
+    assert_eq!(parse_hex("+f"), None);
+    assert_eq!(parse_hex("abc"), None);
+
+    assert_eq!(
+        json_string("a\"b\\c\nd\u{1}é"),
+        "\"a\\\"b\\\\c\\nd\\u0001é\""
+    );
+}
+
+#[test]
+fn test_output_formats() {
+    let dir = TempDir::new();
+    let a = dir.file("a", b"first");
+    let digest = kupyna_hash(b"first", 384);
+
+    let (code, stdout, _) = kupynasum(&["--tag", "-l384", &a], b"");
+    assert_eq!(code, 0);
+    assert_eq!(
+        stdout,
+        format!("KUPYNA-384 ({}) = {}\n", a, to_hex(&digest))
+    );
+
+    let (_, stdout, _) = kupynasum(&["--tag", "--base64", "-l384", &a], b"");
+    assert_eq!(
+        stdout,
+        format!("KUPYNA-384 ({}) = {}\n", a, to_base64(&digest))
+    );
+
+    let (_, stdout, _) = kupynasum(&["--base64", "-l384", &a], b"");
+    assert_eq!(stdout, format!("{}  {}\n", to_base64(&digest), a));
+
+    let (_, stdout, _) = kupynasum(&["--json", "--base64", "-l384", "-"], b"first");
+    assert_eq!(
+        stdout,
+        format!(
+            "{{\"file\":\"-\",\"algorithm\":\"KUPYNA-384\",\"digest\":\"{}\"}}\n",
+            to_base64(&digest)
+        )
+    );
+
+    let json = Options {
+        format: Format::Json,
+        ..Options::default()
+    };
+    assert_eq!(
+        format_line(&json, &[0xff], "new\nline".as_ref()),
+        b"{\"file\":\"new\\nline\",\"algorithm\":\"KUPYNA-8\",\"digest\":\"ff\"}"
+    );
+
+    for bad in [
+        &["--tag", "--json"][..],
+        &["--json", "--tag"],
+        &["-c", "--tag"],
+        &["-c", "--json"],
+        &["-c", "--base64"],
+    ] {
+        assert_eq!(kupynasum(bad, b"").0, 1, "{:?}", bad);
+    }
+}
+
+#[test]
+fn test_check_tagged_lines() {
+    let dir = TempDir::new();
+    let a = dir.file("a", b"first");
+    let b = dir.file("b", b"second");
+    let c = dir.file("c", b"third");
+
+    // Tagged and untagged lines of mixed lengths and encodings in one list
+    let (_, tag_short, _) = kupynasum(&["--tag", "-l", "8", &a], b"");
+    let (_, tag_long, _) = kupynasum(&["--tag", "--base64", "-l", "512", &b], b"");
+    let (_, plain, _) = kupynasum(&["--base64", "-l", "256", &c], b"");
+    let wrong = format!("KUPYNA-384 ({}) = {}\n", a, to_hex(&[0; 48]));
+    let list = format!("{}{}{}{}", tag_short, tag_long, plain, wrong);
+    let list = dir.file("list", list.as_bytes());
+
+    let (code, stdout, _) = kupynasum(&["-c", &list], b"");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stdout,
+        format!(
+            "{a}: OK\n{b}: OK\n{c}: OK\n{a}: FAILED\n",
+            a = a,
+            b = b,
+            c = c
+        )
+    );
+
+    // --length keeps only the lines of that length
+    let (code, stdout, _) = kupynasum(&["-c", "-l", "512", &list], b"");
+    assert_eq!((code, stdout), (0, format!("{}: OK\n", b)));
+}
+
+#[test]
+fn test_parse_tagged_line() {
+    let hex = "ab".repeat(48);
+    let entry = parse_line(format!("KUPYNA-384 (name (1)) = {}", hex).as_bytes(), None).unwrap();
+    assert_eq!(entry.name, "name (1)");
+    assert_eq!(entry.digest, parse_hex(&hex).unwrap());
+
+    // 64 hex digits are also 48 bytes of base64, so the tag decides between them
+    let ambiguous = "0".repeat(64);
+    let entry = parse_line(format!("KUPYNA-384 (x) = {}", ambiguous).as_bytes(), None).unwrap();
+    assert_eq!(entry.digest, parse_base64(&ambiguous).unwrap());
+    let entry = parse_line(format!("KUPYNA-256 (x) = {}", ambiguous).as_bytes(), None).unwrap();
+    assert_eq!(entry.digest, vec![0; 32]);
+
+    let escaped = parse_line(format!("\\KUPYNA-384 (a\\nb) = {}", hex).as_bytes(), None).unwrap();
+    assert_eq!(escaped.name, "a\nb");
+
+    for bad in [
+        format!("KUPYNA-384 (x) = {}", "ab".repeat(31)),
+        format!("KUPYNA-250 (x) = {}", "ab".repeat(32)),
+        format!("KUPYNA-384 () = {}", hex),
+        format!("KUPYNA-384 (x) {}", hex),
+        format!("SHA256 (x) = {}", "ab".repeat(32)),
+    ] {
+        assert_eq!(parse_line(bad.as_bytes(), None), None, "{:?}", bad);
+    }
+    assert_eq!(
+        parse_line(format!("KUPYNA-384 (x) = {}", hex).as_bytes(), Some(256)),
+        None
+    );
+}
+
+#[test]
+fn test_parser() {
+    const SHORT: &[(char, &str)] = &[('a', "all"), ('n', "name")];
+    let args = ["-an", "x", "-nvalue", "--name=y", "op", "-", "--", "--all"];
+    let mut parser = options::Parser::new(args.map(OsString::from), SHORT);
+    let mut parsed = Vec::new();
+    while let Some(arg) = parser.next().unwrap() {
+        if arg == Arg::Option("name".to_string()) {
+            parsed.push(format!(
+                "name={}",
+                parser.value().unwrap().to_string_lossy()
+            ));
+        } else {
+            parsed.push(format!("{:?}", arg));
+        }
+    }
+    assert_eq!(
+        parsed,
+        [
+            "Option(\"all\")",
+            "name=x",
+            "name=value",
+            "name=y",
+            "Operand(\"op\")",
+            "Operand(\"-\")",
+            "Operand(\"--all\")",
+        ]
+    );
+
+    let mut parser = options::Parser::new([OsString::from("--all=1")], SHORT);
+    assert!(parser.next().unwrap().is_some());
+    assert_eq!(
+        parser.next(),
+        Err("option '--all' doesn't allow an argument".to_string())
+    );
+    let mut parser = options::Parser::new([OsString::from("-ax")], SHORT);
+    assert!(parser.next().unwrap().is_some());
+    assert_eq!(parser.next(), Err("invalid option -- 'x'".to_string()));
+    let mut parser = options::Parser::new([OsString::from("-n")], SHORT);
+    assert!(parser.next().unwrap().is_some());
+    assert_eq!(
+        parser.value(),
+        Err("option requires an argument -- 'n'".to_string())
+    );
+
+    // Subcommand names are files after --
+    assert!(matches!(
+        options::parse(["--", "manifest"].map(OsString::from)),
+        Ok(Command::Sum(o)) if o.files == ["manifest"]
+    ));
+}
+
+/// A small release tree, with names that sort differently per directory and per path.
+fn release_tree() -> TempDir {
+    let dir = TempDir::new();
+    dir.file("tree/a.txt", b"a");
+    dir.file("tree/a/b", b"b");
+    dir.file("tree/a/c/d", b"d");
+    dir.file("tree/B", b"upper");
+    dir.file("tree/empty", b"");
+    fs::create_dir_all(dir.0.join("tree/no files")).unwrap();
+    dir
+}
+
+#[test]
+fn test_manifest() {
+    let dir = release_tree();
+    let tree = dir.0.join("tree");
+    let manifest = build(&tree, 256, 1, None).unwrap();
+    let expected: String = [
+        ("B", &b"upper"[..]),
+        ("a.txt", b"a"),
+        ("a/b", b"b"),
+        ("a/c/d", b"d"),
+        ("empty", b""),
+    ]
+    .iter()
+    .map(|(path, contents)| format!("{}  {}\n", to_hex(&kupyna_hash(contents, 256)), path))
+    .collect();
+    assert_eq!(manifest, expected);
+
+    let tree = tree.to_str().unwrap();
+    let (code, stdout, _) = kupynasum(&["manifest", tree], b"");
+    assert_eq!((code, &stdout), (0, &manifest));
+    let (code, stdout, _) = kupynasum(&["manifest", "-r", tree], b"");
+    assert_eq!(code, 0);
+    assert_eq!(
+        stdout,
+        format!("{}  {}\n", to_hex(&root_digest(&manifest, 256)), tree)
+    );
+    assert_eq!(
+        root_digest(&manifest, 256),
+        kupyna_hash(manifest.as_bytes(), 256)
+    );
+
+    let (_, stdout, _) = kupynasum(&["manifest", "--length=512", tree], b"");
+    assert_eq!(parse_manifest(&stdout, Some(512)).unwrap().len(), 5);
+
+    // The same contents elsewhere give the same root
+    let copy = release_tree();
+    assert_eq!(build(&copy.0.join("tree"), 256, 1, None).unwrap(), manifest);
+
+    for bad in [
+        &["manifest"][..],
+        &["manifest", "a", "b"],
+        &["manifest", "--root", "--verify", "m", "a"],
+        &["manifest", "--bogus", "a"],
+    ] {
+        assert_eq!(kupynasum(bad, b"").0, 1, "{:?}", bad);
+    }
+    let missing = dir.0.join("missing");
+    let (code, _, stderr) = kupynasum(&["manifest", missing.to_str().unwrap()], b"");
+    assert_eq!(code, 1);
+    assert!(stderr.ends_with("No such file or directory\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_manifest_symlinks() {
+    let dir = release_tree();
+    let tree = dir.0.join("tree");
+    std::os::unix::fs::symlink("a.txt", tree.join("link")).unwrap();
+    std::os::unix::fs::symlink("a", tree.join("dir link")).unwrap();
+    std::os::unix::fs::symlink("nowhere", tree.join("dangling")).unwrap();
+
+    let manifest = build(&tree, 256, 1, None).unwrap();
+    let entries = parse_manifest(&manifest, None).unwrap();
+    assert_eq!(entries["link"], entries["a.txt"]);
+    assert!(!entries
+        .keys()
+        .any(|path| path.starts_with("dir link") || path == "dangling"));
+}
+
+#[test]
+fn test_manifest_verify() {
+    let dir = release_tree();
+    let tree = dir.0.join("tree");
+    let manifest = dir.file("MANIFEST", build(&tree, 256, 1, None).unwrap().as_bytes());
+    let tree_arg = tree.to_str().unwrap();
+
+    let (code, stdout, stderr) = kupynasum(&["manifest", "--verify", &manifest, tree_arg], b"");
+    assert_eq!((code, stdout.as_str(), stderr.as_str()), (0, "", ""));
+
+    dir.file("tree/a/c/d", b"changed");
+    dir.file("tree/a/new", b"new");
+    dir.file("tree/new\nline", b"new");
+    fs::remove_file(tree.join("B")).unwrap();
+
+    let expected = parse_manifest(&fs::read_to_string(&manifest).unwrap(), None).unwrap();
+    assert_eq!(
+        verify(&expected, &tree, 1).unwrap(),
+        [
+            ("B".to_string(), Change::Removed),
+            ("a/c/d".to_string(), Change::Modified),
+            ("a/new".to_string(), Change::Added),
+            ("new\nline".to_string(), Change::Added),
+        ]
+    );
+
+    let (code, stdout, _) = kupynasum(
+        &["manifest", "--verify=-", tree_arg],
+        &fs::read(&manifest).unwrap(),
+    );
+    assert_eq!(code, 1);
+    assert_eq!(
+        stdout,
+        "removed: B\nmodified: a/c/d\nadded: a/new\n\\added: new\\nline\n"
+    );
+
+    let bad = dir.file("BAD", b"# comment\nnot a manifest line\n");
+    let (code, _, stderr) = kupynasum(&["manifest", "--verify", &bad, tree_arg], b"");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stderr,
+        format!(
+            "kupynasum: {}: 2: improperly formatted manifest line\n",
+            bad
+        )
+    );
+    let line = format!("{}  x\n", to_hex(&[0; 32]));
+    assert!(parse_manifest(&line.repeat(2), None).is_err());
+}
+
+#[test]
+fn test_compare_trees() {
+    let tree = |files: &[(&str, &[u8])]| -> Vec<(String, Vec<u8>)> {
+        files
+            .iter()
+            .map(|(path, contents)| (path.to_string(), kupyna_hash(contents, 256)))
+            .collect()
+    };
+    let left = tree(&[
+        ("same", b"same"),
+        ("edited", b"before"),
+        ("gone", b"gone"),
+        ("old name", b"renamed"),
+        ("copy 1", b"copied"),
+        ("copy 2", b"copied"),
+    ]);
+    let right = tree(&[
+        ("same", b"same"),
+        ("edited", b"after"),
+        ("new", b"new"),
+        ("new name", b"renamed"),
+        ("z copy", b"copied"),
+    ]);
+
+    let differences: Vec<Difference> = compare(&left, &right)
+        .into_iter()
+        .map(|(difference, _)| difference)
+        .collect();
+    let s = String::from;
+    assert_eq!(
+        differences,
+        [
+            // Of two left files with the same contents, the first in path order moved
+            Difference::Moved(s("copy 1"), s("z copy")),
+            Difference::LeftOnly(s("copy 2")),
+            Difference::Changed(s("edited")),
+            Difference::LeftOnly(s("gone")),
+            Difference::RightOnly(s("new")),
+            Difference::Moved(s("old name"), s("new name")),
+            Difference::Identical(s("same")),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_command() {
+    let dir = TempDir::new();
+    dir.file("left/a", b"a");
+    dir.file("left/sub/b", b"b");
+    dir.file("left/c", b"c");
+    dir.file("right/a", b"a");
+    dir.file("right/moved/b", b"b");
+    dir.file("right/c", b"changed");
+    let left = dir.0.join("left").to_str().unwrap().to_string();
+    let right = dir.0.join("right").to_str().unwrap().to_string();
+
+    let (code, stdout, _) = kupynasum(&["diff", &left, &right], b"");
+    assert_eq!(code, 1);
+    assert_eq!(stdout, "changed: c\nmoved: sub/b -> moved/b\n");
+
+    let (_, stdout, _) = kupynasum(&["diff", "-s", &left, &right], b"");
+    assert_eq!(
+        stdout,
+        "identical: a\nchanged: c\nmoved: sub/b -> moved/b\n"
+    );
+
+    let (_, stdout, _) = kupynasum(&["diff", "--json", "-l", "8", &right, &left], b"");
+    let hex = |contents: &[u8]| to_hex(&kupyna_hash(contents, 8));
+    assert_eq!(
+        stdout,
+        format!(
+            "{{\"status\":\"changed\",\"left\":{{\"path\":\"c\",\"digest\":\"{}\"}},\
+             \"right\":{{\"path\":\"c\",\"digest\":\"{}\"}}}}\n\
+             {{\"status\":\"moved\",\"left\":{{\"path\":\"moved/b\",\"digest\":\"{b}\"}},\
+             \"right\":{{\"path\":\"sub/b\",\"digest\":\"{b}\"}}}}\n",
+            hex(b"changed"),
+            hex(b"c"),
+            b = hex(b"b")
+        )
+    );
+
+    let (code, stdout, _) = kupynasum(&["diff", &left, &left], b"");
+    assert_eq!((code, stdout.as_str()), (0, ""));
+
+    dir.file("only/x", b"x");
+    let only = dir.0.join("only").to_str().unwrap().to_string();
+    let (_, stdout, _) = kupynasum(&["diff", "--json", &only, &left], b"");
+    assert!(stdout.starts_with("{\"status\":\"right-only\",\"left\":null,"));
+    assert!(stdout.ends_with("\"right\":null}\n"));
+
+    let missing = dir.0.join("missing").to_str().unwrap().to_string();
+    assert_eq!(kupynasum(&["diff", &left, &missing], b"").0, 2);
+    assert_eq!(kupynasum(&["diff", &left], b"").0, 1);
+    assert_eq!(kupynasum(&["diff", &left, &right, &left], b"").0, 1);
+}
+
+#[test]
+fn test_find_duplicates() {
+    let dir = TempDir::new();
+    let big = vec![7; PREFIX_LEN as usize + 10];
+    let mut big_tail = big.clone();
+    *big_tail.last_mut().unwrap() = 8;
+    let files = [
+        dir.file("small 1", b"small"),
+        dir.file("small 2", b"small"),
+        dir.file("same size", b"smell"),
+        dir.file("big 1", &big),
+        dir.file("sub/big 2", &big),
+        dir.file("big tail", &big_tail),
+        dir.file("empty 1", b""),
+        dir.file("empty 2", b""),
+        dir.file("unique", b"unique"),
+    ];
+
+    let mut errors = Vec::new();
+    let mut paths: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+    paths.push(dir.0.join("missing"));
+    let sets = find_duplicates(paths, 256, 1, None, &mut errors);
+    assert_eq!(
+        sets,
+        [
+            DuplicateSet {
+                digest: kupyna_hash(&big, 256),
+                paths: vec![PathBuf::from(&files[3]), PathBuf::from(&files[4])],
+            },
+            DuplicateSet {
+                digest: kupyna_hash(b"small", 256),
+                paths: vec![PathBuf::from(&files[0]), PathBuf::from(&files[1])],
+            },
+        ]
+    );
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].ends_with("missing: No such file or directory"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_dupes_command_links() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = TempDir::new();
+    let a = dir.file("tree/a", b"contents");
+    let b = dir.file("tree/sub/b", b"contents");
+    let c = dir.file("other/c", b"contents");
+    dir.file("tree/d", b"different");
+    std::os::unix::fs::symlink(&a, dir.0.join("tree/link")).unwrap();
+    let tree = dir.0.join("tree").to_str().unwrap().to_string();
+    let other = dir.0.join("other").to_str().unwrap().to_string();
+
+    let hex = to_hex(&kupyna_hash(b"contents", 256));
+    let listing = format!("{h}  {}\n{h}  {}\n{h}  {}\n\n", c, a, b, h = hex);
+    let (code, stdout, stderr) = kupynasum(&["dupes", &tree, &other], b"");
+    assert_eq!(
+        (code, stdout.as_str(), stderr.as_str()),
+        (0, listing.as_str(), "")
+    );
+
+    let (code, stdout, _) = kupynasum(&["dupes", "--link", &tree, &other], b"");
+    assert_eq!((code, stdout), (0, listing));
+    let inode = |path: &str| fs::metadata(path).unwrap().ino();
+    assert_eq!(inode(&a), inode(&c));
+    assert_eq!(inode(&b), inode(&c));
+    assert_eq!(fs::read(&b).unwrap(), b"contents");
+    assert!(fs::symlink_metadata(dir.0.join("tree/link"))
+        .unwrap()
+        .is_symlink());
+
+    // Linked files are one file now
+    let (code, stdout, _) = kupynasum(&["dupes", &tree, &other], b"");
+    assert_eq!((code, stdout.as_str()), (0, ""));
+
+    assert_eq!(kupynasum(&["dupes"], b"").0, 1);
+}
+
+#[test]
+fn test_dupes_link_refuses_short_digests() {
+    let dir = TempDir::new();
+    dir.file("tree/a", b"contents");
+    let tree = dir.0.join("tree");
+    let tree = tree.to_str().unwrap();
+    let (code, stdout, stderr) = kupynasum(&["dupes", "--link", "-l", "128", tree], b"");
+    assert_eq!((code, stdout.as_str()), (1, ""));
+    assert!(stderr.contains("--link needs a length of at least 256 bits"));
+    assert_eq!(kupynasum(&["dupes", "-l", "128", tree], b"").0, 0);
+}
+
+#[test]
+fn test_dupes_link_compares_contents() {
+    // Two different files of the same size whose 8-bit digests collide
+    let mut seen = std::collections::HashMap::new();
+    let (first, second) = (0u32..)
+        .map(|i| format!("file {:04}", i).into_bytes())
+        .find_map(|contents| {
+            let digest = kupyna_hash(&contents, 8);
+            seen.insert(digest, contents.clone())
+                .map(|other| (other, contents))
+        })
+        .unwrap();
+    let dir = TempDir::new();
+    let a = dir.file("tree/a", &first);
+    let b = dir.file("tree/b", &second);
+    let options = DupesOptions {
+        length: 8,
+        link: true,
+        no_cache: true,
+        dirs: vec![dir.0.join("tree")],
+        ..DupesOptions::default()
+    };
+
+    // The parser refuses such a short length, but linking must not trust it either
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    assert!(!dupes::run(&options, &mut stdout, &mut stderr).unwrap());
+    let listing = format!(
+        "{h}  {}\n{h}  {}\n\n",
+        a,
+        b,
+        h = to_hex(&kupyna_hash(&first, 8))
+    );
+    assert_eq!(String::from_utf8(stdout).unwrap(), listing);
+    assert!(String::from_utf8(stderr)
+        .unwrap()
+        .contains("contents differ"));
+    assert_eq!(fs::read(&a).unwrap(), first);
+    assert_eq!(fs::read(&b).unwrap(), second);
+    assert_eq!(fs::read_dir(dir.0.join("tree")).unwrap().count(), 2);
+
+    // Files that are the same are still linked
+    let c = dir.file("c", &first);
+    link(Path::new(&a), Path::new(&c)).unwrap();
+    assert_eq!(fs::read(&c).unwrap(), first);
+    assert!(link(Path::new(&a), Path::new(&b)).is_err());
+    assert_eq!(fs::read(&b).unwrap(), second);
+}
+
+#[test]
+fn test_map_ordered() {
+    // Later items finish first, and still come out in order
+    let items: Vec<u64> = (0..16).collect();
+    let mut results = Vec::new();
+    let work = |i: u64| {
+        std::thread::sleep(std::time::Duration::from_millis(16 - i));
+        i * i
+    };
+    map_ordered(items.clone(), 4, work, |r| {
+        results.push(r);
+        Ok::<_, ()>(())
+    })
+    .unwrap();
+    assert_eq!(results, items.iter().map(|i| i * i).collect::<Vec<_>>());
+
+    // An error from emit stops the run
+    let started = AtomicUsize::new(0);
+    let work = |i: usize| {
+        started.fetch_add(1, Ordering::Relaxed);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        i
+    };
+    let result = map_ordered((0..1000).collect(), 4, work, |i| match i {
+        3 => Err(i),
+        _ => Ok(()),
+    });
+    assert_eq!(result, Err(3));
+    assert!(started.load(Ordering::Relaxed) < 1000);
+}
+
+#[test]
+fn test_jobs() {
+    let dir = TempDir::new();
+    let mut files: Vec<String> = (0..40)
+        .map(|i| dir.file(&format!("tree/{:02}", i), &vec![i as u8; i * 1000]))
+        .collect();
+    files.insert(5, dir.0.join("missing").to_str().unwrap().to_string());
+    files.insert(10, "-".to_string());
+    files.insert(20, "-".to_string());
+    let args: Vec<&str> = files.iter().map(String::as_str).collect();
+
+    let one = kupynasum(&[&["-j1"][..], &args].concat(), b"stdin");
+    let many = kupynasum(&[&["--jobs", "8"][..], &args].concat(), b"stdin");
+    assert_eq!(one.0, 1);
+    assert_eq!(one, many);
+    assert_eq!(many.2.lines().count(), 1);
+    // Only the first - reads what was there
+    let lines: Vec<&str> = many.1.lines().collect();
+    assert_eq!(
+        lines[9],
+        format!("{}  -", to_hex(&kupyna_hash(b"stdin", 256)))
+    );
+    assert_eq!(lines[19], format!("{}  -", to_hex(&kupyna_hash(b"", 256))));
+
+    let list = dir.file("list", one.1.as_bytes());
+    let one = kupynasum(&["-c", "-j", "1", &list], b"");
+    let many = kupynasum(&["-c", "-j", "0", &list], b"");
+    assert_eq!(one, many);
+    // The files, and the second - since standard input is empty again
+    assert_eq!(
+        many.1.lines().filter(|line| line.ends_with(": OK")).count(),
+        41
+    );
+
+    let tree = dir.0.join("tree");
+    assert_eq!(build(&tree, 256, 1, None), build(&tree, 256, 8, None));
+    let paths = || files.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let mut errors = Vec::new();
+    assert_eq!(
+        find_duplicates(paths(), 256, 1, None, &mut errors),
+        find_duplicates(paths(), 256, 8, None, &mut errors)
+    );
+
+    assert_eq!(kupynasum(&["-j", "x"], b"").0, 1);
+}
+
+/// Sets a file's modification time an hour back, so it is old enough to cache.
+fn age(path: &str) {
+    let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    let file = fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(hour_ago).unwrap();
+}
+
+#[test]
+fn test_cache() {
+    let dir = TempDir::new();
+    let a = dir.file("tree/a", b"alpha");
+    let fresh = dir.file("tree/fresh", b"fresh");
+    age(&a);
+    let cache = dir.0.join("cache");
+    let cache = cache.to_str().unwrap();
+    let real = format!("{}  {}\n", to_hex(&kupyna_hash(b"alpha", 256)), a);
+
+    let (code, stdout, _) = kupynasum(&["--cache", cache, &a, &fresh], b"");
+    assert_eq!(code, 0);
+    assert!(stdout.starts_with(&real));
+    // Only the file old enough to trust is recorded
+    let contents = fs::read_to_string(cache).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "kupynasum cache 1");
+    assert!(lines[1].starts_with("256 5 ") && lines[1].ends_with(&format!(" {}", a)));
+
+    // A forged digest shows the cache is read instead of the file
+    let forged = contents.replace(&to_hex(&kupyna_hash(b"alpha", 256)), &"00".repeat(32));
+    fs::write(cache, &forged).unwrap();
+    let zeros = format!("{}  {}\n", "00".repeat(32), a);
+    assert_eq!(kupynasum(&["--cache", cache, &a], b"").1, zeros);
+    assert_eq!(
+        kupynasum(&["--cache", cache, "-j4", &a, &a], b"").1,
+        zeros.repeat(2)
+    );
+    assert_eq!(
+        kupynasum(&["--cache", cache, "--no-cache", &a], b"").1,
+        real
+    );
+    // Other lengths are separate entries
+    assert_eq!(
+        kupynasum(&["--cache", cache, "-l512", &a], b"").1,
+        format!("{}  {}\n", to_hex(&kupyna_hash(b"alpha", 512)), a)
+    );
+    let dupe = dir.file("tree/b", b"alpha");
+    age(&dupe);
+    let root = dir.0.join("tree");
+    let root = root.to_str().unwrap();
+    assert_eq!(
+        kupynasum(&["manifest", "--cache", cache, root], b"").1,
+        format!(
+            "{}  a\n{}  b\n{}  fresh\n",
+            "00".repeat(32),
+            to_hex(&kupyna_hash(b"alpha", 256)),
+            to_hex(&kupyna_hash(b"fresh", 256))
+        )
+    );
+
+    // Verifying reads the files whatever the cache says
+    let list = dir.file("list", real.as_bytes());
+    assert_eq!(kupynasum(&["-c", &list], b"").0, 0);
+    assert_eq!(kupynasum(&["-c", "--cache", cache, &list], b"").0, 1);
+    assert_eq!(
+        kupynasum(
+            &["manifest", "--cache", cache, "--verify", &list, root],
+            b""
+        )
+        .0,
+        1
+    );
+
+    // Changing the file makes its entry stale
+    fs::write(&a, b"alpha, changed").unwrap();
+    age(&a);
+    assert_eq!(
+        kupynasum(&["--cache", cache, &a], b"").1,
+        format!("{}  {}\n", to_hex(&kupyna_hash(b"alpha, changed", 256)), a)
+    );
+    let contents = fs::read_to_string(cache).unwrap();
+    assert!(!contents.contains(&"00".repeat(32)));
+
+    // A damaged cache is ignored rather than trusted
+    fs::write(cache, "kupynasum cache 1\n256 x\nnot a cache line\n").unwrap();
+    assert_eq!(kupynasum(&["--cache", cache, &dupe], b"").0, 0);
+    fs::write(cache, "some other file\n").unwrap();
+    assert_eq!(kupynasum(&["--cache", cache, &dupe], b"").0, 0);
+    assert!(fs::read_to_string(cache)
+        .unwrap()
+        .starts_with("kupynasum cache 1\n"));
+}
+
+#[test]
+fn test_mac_commands() {
+    let dir = TempDir::new();
+    let data = dir.file("data", b"payload");
+    let key = dir.file("key", b"secret key");
+    let hex_key = dir.file("key.hex", format!("{}\n", to_hex(b"secret key")).as_bytes());
+    let kmac_key: Vec<u8> = (0..32).collect();
+    let kmac_key_file = dir.file("kmac.key", &kmac_key);
+    let hmac = to_hex(&hmac_kupyna(b"secret key", b"payload", 256));
+    let kmac = to_hex(&kmac_kupyna(&kmac_key, b"payload", 256).unwrap());
+
+    assert_eq!(
+        kupynasum(&["hmac", "--key-file", &key, &data], b""),
+        (0, format!("{}  {}\n", hmac, data), String::new())
+    );
+    assert_eq!(
+        kupynasum(
+            &["hmac", "--key-file", &hex_key, "--hex-key", "-"],
+            b"payload"
+        )
+        .1,
+        format!("{}  -\n", hmac)
+    );
+    assert_eq!(
+        kupynasum(&["kmac", "--key-file", &kmac_key_file, "--tag", &data], b"").1,
+        format!("KMAC-KUPYNA-256 ({}) = {}\n", data, kmac)
+    );
+    assert_eq!(
+        kupynasum(
+            &[
+                "hmac",
+                "--key-file",
+                &key,
+                "-l512",
+                "--json",
+                "-j2",
+                &data,
+                &data
+            ],
+            b""
+        )
+        .1,
+        format!(
+            "{{\"file\":{},\"algorithm\":\"HMAC-KUPYNA-512\",\"digest\":\"{}\"}}\n",
+            json_string(&data),
+            to_hex(&hmac_kupyna(b"secret key", b"payload", 512))
+        )
+        .repeat(2)
+    );
+
+    std::env::set_var("KUPYNASUM_TEST_HMAC_KEY", to_hex(b"secret key"));
+    assert_eq!(
+        kupynasum(
+            &[
+                "hmac",
+                "--key-env",
+                "KUPYNASUM_TEST_HMAC_KEY",
+                "--hex-key",
+                &data
+            ],
+            b""
+        )
+        .1,
+        format!("{}  {}\n", hmac, data)
+    );
+
+    // Checking, in both layouts, and failing on a forged code
+    let codes = kupynasum(&["hmac", "--key-file", &key, &data], b"").1;
+    let list = dir.file("codes", codes.as_bytes());
+    let check = |args: &[&str]| {
+        kupynasum(
+            &[&["hmac", "--key-file", &key, "-c"][..], args].concat(),
+            b"",
+        )
+    };
+    assert_eq!(
+        check(&[&list]),
+        (0, format!("{}: OK\n", data), String::new())
+    );
+    let tagged = kupynasum(&["hmac", "--key-file", &key, "--tag", &data], b"").1;
+    assert_eq!(check(&[&dir.file("tagged", tagged.as_bytes())]).0, 0);
+    // A plain checksum line is not a code line
+    let plain = kupynasum(&["--tag", &data], b"").1;
+    assert_eq!(check(&[&dir.file("plain", plain.as_bytes())]).0, 1);
+    let forged = dir.file(
+        "forged",
+        codes
+            .replacen(&hmac[..2], if &hmac[..2] == "00" { "01" } else { "00" }, 1)
+            .as_bytes(),
+    );
+    let (code, stdout, stderr) = check(&[&forged]);
+    assert_eq!(code, 1);
+    assert_eq!(stdout, format!("{}: FAILED\n", data));
+    assert!(stderr.contains("1 computed checksum did NOT match"));
+    let other_key = dir.file("other.key", b"another key");
+    assert_eq!(
+        kupynasum(&["hmac", "--key-file", &other_key, "-c", &list], b"").0,
+        1
+    );
+    let kmac_list = dir.file("kmac", format!("{}  {}\n", kmac, data).as_bytes());
+    assert_eq!(
+        kupynasum(
+            &[
+                "kmac",
+                "--key-file",
+                &kmac_key_file,
+                "-c",
+                "--status",
+                &kmac_list
+            ],
+            b""
+        ),
+        (0, String::new(), String::new())
+    );
+
+    // Keys: never from the arguments, exactly one source, and usable
+    let (code, _, stderr) = kupynasum(&["hmac", "--key=secret", &data], b"");
+    assert_eq!(code, 1);
+    assert!(stderr.contains("keys are not taken as arguments"));
+    assert!(kupynasum(&["hmac", &data], b"")
+        .2
+        .contains("a key is required"));
+    assert!(
+        kupynasum(&["hmac", "--key-file", &key, "--key-env", "K", &data], b"")
+            .2
+            .contains("only one of")
+    );
+    assert_eq!(kupynasum(&["--key-file", &key, &data], b"").0, 1);
+    assert_eq!(
+        kupynasum(&["hmac", "--key-file", &key, "--cache", "c", &data], b"").0,
+        1
+    );
+    assert!(
+        kupynasum(&["hmac", "--key-file", &data, "--hex-key", &data], b"")
+            .2
+            .contains("key is not valid hex")
+    );
+    assert!(
+        kupynasum(&["hmac", "--key-file", &dir.file("empty", b""), &data], b"")
+            .2
+            .contains("key is empty")
+    );
+    assert!(kupynasum(
+        &["hmac", "--key-env", "KUPYNASUM_TEST_UNSET_KEY", &data],
+        b""
+    )
+    .2
+    .contains("is not set"));
+    let (code, _, stderr) = kupynasum(&["kmac", "--key-file", &key, &data], b"");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stderr,
+        format!(
+            "kupynasum: {}: KMAC keys are 32, 48 or 64 bytes, not 10\n",
+            key
+        )
+    );
+    assert!(
+        kupynasum(&["kmac", "--key-file", &kmac_key_file, "-l512", &data], b"")
+            .2
+            .contains("KMAC-512 needs a 64-byte key")
+    );
+    assert!(kupynasum(&["hmac", "--help"], b"")
+        .1
+        .starts_with("Usage: kupynasum hmac"));
+}
+
+#[test]
+fn test_kdf_commands() {
+    let dir = TempDir::new();
+    let secret = dir.file("secret", b"master secret");
+    let hkdf = hkdf_kupyna(b"master secret", b"salt", b"billing", 32, 256).unwrap();
+
+    assert_eq!(
+        kupynasum(
+            &["hkdf", "--salt", "salt", "--info", "billing"],
+            b"master secret"
+        ),
+        (0, format!("{}\n", to_hex(&hkdf)), String::new())
+    );
+    let args = [
+        "hkdf",
+        "--secret-file",
+        &secret,
+        "--salt-hex",
+        "73616c74",
+        "--info-hex",
+        "62696c6c696e67",
+    ];
+    assert_eq!(kupynasum(&args, b"").1, format!("{}\n", to_hex(&hkdf)));
+    assert_eq!(
+        kupynasum(&[&args[..], &["--base64"]].concat(), b"").1,
+        format!("{}\n", to_base64(&hkdf))
+    );
+    let mut raw = Vec::new();
+    let raw_args = [&args[..], &["-n", "100", "-l", "512", "--raw"]].concat();
+    let code = run(
+        raw_args.iter().map(OsString::from),
+        &mut &b""[..],
+        &mut raw,
+        &mut Vec::new(),
+    );
+    assert_eq!(code, 0);
+    assert_eq!(
+        raw,
+        hkdf_kupyna(b"master secret", b"salt", b"billing", 100, 512).unwrap()
+    );
+    // The secret is used as read, newline and all
+    assert_ne!(
+        kupynasum(&["hkdf"], b"master secret\n").1,
+        kupynasum(&["hkdf"], b"master secret").1
+    );
+
+    let pbkdf2 = pbkdf2_kupyna(b"hunter2", b"NaCl", 1000, 48, 256);
+    assert_eq!(
+        kupynasum(&["pbkdf2", "-i", "1000", "--salt=NaCl", "-n48"], b"hunter2").1,
+        format!("{}\n", to_hex(&pbkdf2))
+    );
+
+    let error = |args: &[&str], stdin: &[u8]| {
+        let (code, stdout, stderr) = kupynasum(args, stdin);
+        assert_eq!((code, stdout.as_str()), (1, ""), "{:?}", args);
+        stderr
+    };
+    assert!(error(&["pbkdf2"], b"x").contains("--iterations is required"));
+    assert!(error(&["pbkdf2", "-i", "0"], b"x").contains("invalid number of iterations"));
+    assert!(error(&["pbkdf2", "-i", "1", "--info", "x"], b"x").contains("unrecognized option"));
+    assert!(error(&["hkdf", "-i", "1"], b"x").contains("unrecognized option '-i'"));
+    assert!(error(&["hkdf", "hunter2"], b"x").contains("extra operand 'hunter2'"));
+    assert!(error(&["hkdf", "--salt-hex", "xyz"], b"x").contains("invalid hex salt"));
+    assert!(error(&["hkdf", "--base64", "--raw"], b"x").contains("mutually exclusive"));
+    assert!(error(&["hkdf", "-n", "0"], b"x").contains("invalid number of bytes"));
+    assert!(error(&["hkdf"], b"").contains("standard input: secret is empty"));
+    assert!(error(&["hkdf", "-n", "8161"], b"x").contains("at most 8160 bytes"));
+    let missing = dir.0.join("missing");
+    assert!(
+        error(&["hkdf", "--secret-file", missing.to_str().unwrap()], b"x")
+            .contains("No such file or directory")
+    );
+    assert!(kupynasum(&["pbkdf2", "--help"], b"")
+        .1
+        .starts_with("Usage: kupynasum hkdf"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_names_round_trip() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let run_bytes = |args: Vec<OsString>| {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let code = run(args, &mut &b""[..], &mut stdout, &mut stderr);
+        (code, stdout, String::from_utf8(stderr).unwrap())
+    };
+    let dir = TempDir::new();
+    // Latin-1, so not UTF-8, and with a backslash to escape
+    let path = dir.0.join(OsStr::from_bytes(b"caf\xe9\\1"));
+    fs::write(&path, b"abc").unwrap();
+
+    let (code, line, _) = run_bytes(vec![path.clone().into()]);
+    assert_eq!(code, 0);
+    let name = path.as_os_str().as_bytes();
+    let escaped = [&name[..name.len() - 6], b"caf\xe9\\\\1"].concat();
+    let hex = to_hex(&kupyna_hash(b"abc", 256));
+    assert_eq!(
+        line,
+        [b"\\", hex.as_bytes(), b"  ", &escaped, b"\n"].concat()
+    );
+
+    // Checking finds the same file from the line
+    let list = dir.0.join("list");
+    fs::write(&list, &line).unwrap();
+    let (code, result, _) = run_bytes(vec!["-c".into(), list.into()]);
+    assert_eq!(
+        (code, result),
+        (0, [b"\\", &escaped[..], b": OK\n"].concat())
+    );
+
+    // JSON cannot hold the name, and values that must be text are refused
+    let (code, json, stderr) = run_bytes(vec!["--json".into(), path.into()]);
+    assert_eq!((code, json), (1, Vec::new()));
+    assert!(stderr.contains("not valid UTF-8"), "{}", stderr);
+    let (code, _, stderr) = run_bytes(vec!["-l".into(), OsStr::from_bytes(b"2\xe9").into()]);
+    assert_eq!(code, 1);
+    assert!(stderr.starts_with("kupynasum: invalid argument '2\u{fffd}' for '-l'"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_dupes_link_ignores_cache() {
+    let dir = TempDir::new();
+    // The same first block, so only the full digests tell them apart
+    let mut contents = vec![7; PREFIX_LEN as usize + 10];
+    let a = dir.file("tree/a", &contents);
+    contents[PREFIX_LEN as usize] = 8;
+    let b = dir.file("tree/b", &contents);
+    age(&a);
+    age(&b);
+    let cache = dir.0.join("cache");
+    let cache = cache.to_str().unwrap();
+    let tree = dir.0.join("tree");
+    let tree = tree.to_str().unwrap();
+    assert_eq!(kupynasum(&["--cache", cache, &a, &b], b"").0, 0);
+
+    // A cache that says they are the same is believed for listing, but not for linking
+    let [digest_a, digest_b] =
+        [&a, &b].map(|path| to_hex(&kupyna_hash(&fs::read(path).unwrap(), 256)));
+    let forged = fs::read_to_string(cache)
+        .unwrap()
+        .replace(&digest_b, &digest_a);
+    fs::write(cache, forged).unwrap();
+    let listing = format!("{h}  {}\n{h}  {}\n\n", a, b, h = digest_a);
+    assert_eq!(
+        kupynasum(&["dupes", "--cache", cache, tree], b"").1,
+        listing
+    );
+    let (code, stdout, _) = kupynasum(&["dupes", "--link", "--cache", cache, tree], b"");
+    assert_eq!((code, stdout.as_str()), (0, ""));
+    assert_eq!(fs::read(&b).unwrap(), contents);
+}
@@ -0,0 +1,202 @@
+//! Constant-time bitsliced implementation of the T⊕l and T+l permutations.
+//!
+//! Nothing here indexes memory or branches on state-dependent values. Columns are held as
+//! little-endian `u64` words as in `t_table`. For the S-box layer the state is transposed into
+//! eight bit-planes, where bit `8j + i` of plane `p` is bit `p` of row `i` in column `j`, and
+//! each S-box is evaluated as a Boolean circuit from its algebraic normal form. `mix_columns` is
+//! computed on the column words with branch-free doubling in GF(2^8).
+
+use crate::t_xor_plus::{block_to_words, row_shift, words_to_block, COLS_1024, COLS_512, ROWS};
+use crate::tables::SBOXES;
+
+/// Selects every byte position whose row is 0 mod 4; shift left by `s` for S-box `s`.
+const SBOX_ROWS: u128 = 0x11111111111111111111111111111111;
+
+/// `ANF[s][p]` is the algebraic normal form of output bit `p` of S-box `s`, as a 256-bit set:
+/// bit `m` is the coefficient of the monomial made of the input bits set in `m`.
+static ANF: [[[u64; 4]; 8]; 4] = build_anf();
+
+const fn build_anf() -> [[[u64; 4]; 8]; 4] {
+    let mut anf = [[[0u64; 4]; 8]; 4];
+    let mut s = 0;
+    while s < 4 {
+        let mut p = 0;
+        while p < 8 {
+            // Start from the truth table, then apply the Möbius transform in place
+            let mut a = [0u64; 4];
+            let mut x = 0;
+            while x < 256 {
+                a[x / 64] |= (((SBOXES[s][x] >> p) & 1) as u64) << (x % 64);
+                x += 1;
+            }
+            let mut i = 0;
+            while i < 8 {
+                let mut x = 0;
+                while x < 256 {
+                    if x & (1 << i) != 0 {
+                        let y = x ^ (1 << i);
+                        a[x / 64] ^= ((a[y / 64] >> (y % 64)) & 1) << (x % 64);
+                    }
+                    x += 1;
+                }
+                i += 1;
+            }
+            anf[s][p] = a;
+            p += 1;
+        }
+        s += 1;
+    }
+    anf
+}
+
+/// Transposes a column word as an 8x8 bit matrix, so bit `p` of row `i` becomes bit `i` of
+/// byte `p`. The transform is its own inverse.
+fn transpose8(mut x: u64) -> u64 {
+    let t = (x ^ (x >> 7)) & 0x00AA00AA00AA00AA;
+    x ^= t ^ (t << 7);
+    let t = (x ^ (x >> 14)) & 0x0000CCCC0000CCCC;
+    x ^= t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x00000000F0F0F0F0;
+    x ^= t ^ (t << 28);
+    x
+}
+
+fn to_planes<const C: usize>(state: &[u64; C]) -> [u128; 8] {
+    let mut planes = [0u128; 8];
+    for (j, &column) in state.iter().enumerate() {
+        for (p, &byte) in transpose8(column).to_le_bytes().iter().enumerate() {
+            planes[p] |= (byte as u128) << (8 * j);
+        }
+    }
+    planes
+}
+
+fn from_planes<const C: usize>(planes: &[u128; 8]) -> [u64; C] {
+    let mut state = [0u64; C];
+    for (j, column) in state.iter_mut().enumerate() {
+        let mut bytes = [0u8; 8];
+        for (byte, &plane) in bytes.iter_mut().zip(planes) {
+            *byte = (plane >> (8 * j)) as u8;
+        }
+        *column = transpose8(u64::from_le_bytes(bytes));
+    }
+    state
+}
+
+fn s_box_layer(planes: &[u128; 8]) -> [u128; 8] {
+    // Every product of input bits, shared by all four S-boxes and all output bits
+    let mut monomials = [0u128; 256];
+    monomials[0] = !0;
+    for m in 1..256 {
+        let high = 7 - (m as u8).leading_zeros() as usize;
+        monomials[m] = monomials[m ^ (1 << high)] & planes[high];
+    }
+
+    let mut result = [0u128; 8];
+    for (s, anf) in ANF.iter().enumerate() {
+        for (out, coefficients) in result.iter_mut().zip(anf) {
+            let mut y = 0u128;
+            // The coefficients are public constants, so walking their set bits leaks nothing
+            for (w, &word) in coefficients.iter().enumerate() {
+                let mut bits = word;
+                while bits != 0 {
+                    y ^= monomials[w * 64 + bits.trailing_zeros() as usize];
+                    bits &= bits - 1;
+                }
+            }
+            *out |= y & (SBOX_ROWS << s);
+        }
+    }
+    result
+}
+
+fn rotate_rows<const C: usize>(state: &[u64; C]) -> [u64; C] {
+    let mut result = [0u64; C];
+    for (j, column) in result.iter_mut().enumerate() {
+        for row in 0..ROWS {
+            *column |= state[(j + C - row_shift(row, C)) % C] & (0xFF << (8 * row));
+        }
+    }
+    result
+}
+
+/// Multiplies every byte lane by x in GF(2^8) without branches.
+fn xtime(x: u64) -> u64 {
+    ((x & 0x7F7F7F7F7F7F7F7F) << 1) ^ (((x >> 7) & 0x0101010101010101) * 0x1D)
+}
+
+/// Multiplies one column by the circulant MDS matrix with first row
+/// `[0x01, 0x01, 0x05, 0x01, 0x08, 0x06, 0x07, 0x04]`.
+fn mix_column(column: u64) -> u64 {
+    // Rotating right by k bytes lines up row i + k with row i
+    let r = |k: u32| column.rotate_right(8 * k);
+    let x1 = r(0) ^ r(1) ^ r(2) ^ r(3) ^ r(6);
+    let x2 = r(5) ^ r(6);
+    let x4 = r(2) ^ r(5) ^ r(6) ^ r(7);
+    let x8 = r(4);
+    x1 ^ xtime(x2 ^ xtime(x4 ^ xtime(x8)))
+}
+
+fn round<const C: usize>(state: &[u64; C]) -> [u64; C] {
+    let state = from_planes::<C>(&s_box_layer(&to_planes(state)));
+    rotate_rows(&state).map(mix_column)
+}
+
+fn t_xor<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_words::<C>(block);
+    for nu in 0..rounds {
+        for (j, column) in state.iter_mut().enumerate() {
+            *column ^= ((j * 0x10) ^ nu) as u64;
+        }
+        state = round(&state);
+    }
+    words_to_block(state)
+}
+
+fn t_plus<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_words::<C>(block);
+    for nu in 0..rounds {
+        for (j, column) in state.iter_mut().enumerate() {
+            *column = column
+                .wrapping_add(0x00F0F0F0F0F0F0F3u64 ^ (((((C - j - 1) * 0x10) ^ nu) as u64) << 56));
+        }
+        state = round(&state);
+    }
+    words_to_block(state)
+}
+
+/// The T⊕l transformation in constant time.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds to perform.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the transformed block.
+pub fn t_xor_l(block: &[u8], rounds: usize) -> Vec<u8> {
+    if block.len() == COLS_1024 * ROWS {
+        t_xor::<COLS_1024>(block, rounds)
+    } else {
+        t_xor::<COLS_512>(block, rounds)
+    }
+}
+
+/// The T+l transformation in constant time.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds to perform.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the transformed block.
+pub fn t_plus_l(block: &[u8], rounds: usize) -> Vec<u8> {
+    if block.len() == COLS_1024 * ROWS {
+        t_plus::<COLS_1024>(block, rounds)
+    } else {
+        t_plus::<COLS_512>(block, rounds)
+    }
+}
@@ -0,0 +1,408 @@
+//! Reading and writing test vectors in the NIST CAVP `.rsp` format.
+//!
+//! A file is a list of sections, each opened by a `[L = <bytes>]` header giving the hash code
+//! length. Short- and long-message sections hold `Len`, `Msg` and `MD` records, with `Len` in
+//! bits. KMAC sections hold `Len`, `Key`, `Msg` and `Mac` records, with the key as long as the
+//! code and `Len` a whole number of bytes. A Monte Carlo section holds a `Seed` followed by
+//! `COUNT` and `MD` checkpoints, computed as in the SHA-2 validation system: each of the 1000
+//! iterations between checkpoints hashes the concatenation of the previous three digests.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{kmac_kupyna, kupyna_hash_bits};
+
+/// Hashes between Monte Carlo checkpoints.
+const MONTE_CARLO_ITERATIONS: usize = 1000;
+
+/// A test vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Vector {
+    /// A message of `len` bits and its digest.
+    Message {
+        len: usize,
+        msg: Vec<u8>,
+        md: Vec<u8>,
+    },
+    /// A KMAC key, a message of `len` bits and its code.
+    Mac {
+        len: usize,
+        key: Vec<u8>,
+        msg: Vec<u8>,
+        mac: Vec<u8>,
+    },
+    /// A Monte Carlo seed and the digest at each checkpoint.
+    MonteCarlo {
+        seed: Vec<u8>,
+        checkpoints: Vec<Vec<u8>>,
+    },
+}
+
+/// The vectors for one hash code length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The hash code length in bits.
+    pub n: usize,
+    pub vectors: Vec<Vector>,
+}
+
+/// Returned when an `.rsp` file cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line number, counting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// A vector whose digest differs from the one `kupyna_hash` computes, or whose code differs from
+/// the one `kmac_kupyna` computes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The hash code length in bits.
+    pub n: usize,
+    /// The index of the vector within its section.
+    pub index: usize,
+    /// For Monte Carlo vectors, the first checkpoint that differs.
+    pub checkpoint: Option<usize>,
+    pub expected: Vec<u8>,
+    pub actual: Vec<u8>,
+}
+
+/// Parses the contents of an `.rsp` file.
+///
+/// # Arguments
+///
+/// * `input` - The file contents.
+///
+/// # Returns
+///
+/// * The sections in file order, or a `ParseError` for the first malformed line. A record that
+///   a header or the end of the input cuts off before its `MD` or `Mac` is reported at its first
+///   line.
+pub fn parse(input: &str) -> Result<Vec<Section>, ParseError> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut len = None;
+    let mut key = None;
+    let mut msg = None;
+    // The line of the first field of a record still waiting for its `MD` or `Mac`
+    let mut record_start = None;
+    let incomplete = |line: usize| ParseError {
+        line,
+        message: "record ends without `MD` or `Mac`".to_string(),
+    };
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        let error = |message: String| ParseError {
+            line: index + 1,
+            message,
+        };
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if let Some(start) = record_start {
+                return Err(incomplete(start));
+            }
+            // Fields never carry over into the next section
+            (len, key, msg) = (None, None, None);
+            let bytes = match header.split_once('=') {
+                Some((key, value)) if key.trim() == "L" => value.trim().parse::<usize>(),
+                // Other headers do not change how vectors are read
+                _ => continue,
+            };
+            let bytes = bytes.map_err(|e| error(format!("invalid digest length: {}", e)))?;
+            if !(1..=64).contains(&bytes) {
+                return Err(error(format!(
+                    "digest length of {} bytes is not between 1 and 64",
+                    bytes
+                )));
+            }
+            sections.push(Section {
+                n: bytes * 8,
+                vectors: Vec::new(),
+            });
+            continue;
+        }
+
+        let (name, value) = line
+            .split_once('=')
+            .map(|(name, value)| (name.trim(), value.trim()))
+            .ok_or_else(|| error(format!("expected `name = value`, found `{}`", line)))?;
+        let section = sections
+            .last_mut()
+            .ok_or_else(|| error("vector before any `[L = ...]` header".to_string()))?;
+
+        if matches!(name, "Len" | "Key" | "Msg") {
+            record_start.get_or_insert(index + 1);
+        } else if matches!(name, "MD" | "Mac") {
+            record_start = None;
+        }
+        match name {
+            "Len" => {
+                len = Some(
+                    value
+                        .parse()
+                        .map_err(|e| error(format!("invalid length: {}", e)))?,
+                )
+            }
+            "Key" => key = Some(parse_hex(value).map_err(error)?),
+            "Msg" => msg = Some(parse_hex(value).map_err(error)?),
+            "Seed" => section.vectors.push(Vector::MonteCarlo {
+                seed: parse_hex(value).map_err(error)?,
+                checkpoints: Vec::new(),
+            }),
+            "COUNT" => {}
+            "Mac" => {
+                let mac = parse_hex(value).map_err(error)?;
+                let (Some(len), Some(key), Some(msg)) = (len.take(), key.take(), msg.take()) else {
+                    return Err(error("`Mac` needs `Len`, `Key` and `Msg`".to_string()));
+                };
+                if len % 8 != 0 {
+                    return Err(error(format!(
+                        "KMAC message of {} bits is not whole bytes",
+                        len
+                    )));
+                }
+                let msg = message_bits(msg, len).map_err(error)?;
+                section.vectors.push(Vector::Mac { len, key, msg, mac });
+            }
+            "MD" => {
+                let md = parse_hex(value).map_err(error)?;
+                if key.is_some() {
+                    return Err(error("`MD` after `Key`; KMAC codes are `Mac`".to_string()));
+                }
+                match (len.take(), msg.take()) {
+                    (Some(len), Some(msg)) => {
+                        let msg = message_bits(msg, len).map_err(error)?;
+                        section.vectors.push(Vector::Message { len, msg, md });
+                    }
+                    (None, None) => match section.vectors.last_mut() {
+                        Some(Vector::MonteCarlo { checkpoints, .. }) => checkpoints.push(md),
+                        _ => return Err(error("`MD` without `Msg` or `Seed`".to_string())),
+                    },
+                    _ => return Err(error("`MD` needs both `Len` and `Msg`".to_string())),
+                }
+            }
+            _ => return Err(error(format!("unknown field `{}`", name))),
+        }
+    }
+
+    match record_start {
+        Some(start) => Err(incomplete(start)),
+        None => Ok(sections),
+    }
+}
+
+/// Trims a parsed `Msg` to `len` bits, dropping the placeholder byte of an empty message.
+fn message_bits(mut msg: Vec<u8>, len: usize) -> Result<Vec<u8>, String> {
+    if msg.len() * 8 < len {
+        return Err(format!("`Msg` is shorter than {} bits", len));
+    }
+    msg.truncate(len.div_ceil(8));
+    Ok(msg)
+}
+
+/// Checks every vector against `kupyna_hash`, or `kmac_kupyna` for KMAC vectors. A KMAC key
+/// that is not as long as the code is reported as a mismatch with an empty code.
+///
+/// # Arguments
+///
+/// * `sections` - The parsed sections.
+///
+/// # Returns
+///
+/// * A `Vec<Mismatch>` with every vector that failed, empty if they all passed.
+pub fn check(sections: &[Section]) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    for section in sections {
+        for (index, vector) in section.vectors.iter().enumerate() {
+            let mismatch = |checkpoint, expected: &[u8], actual: Vec<u8>| Mismatch {
+                n: section.n,
+                index,
+                checkpoint,
+                expected: expected.to_vec(),
+                actual,
+            };
+            match vector {
+                Vector::Message { len, msg, md } => {
+                    let actual = kupyna_hash_bits(msg, *len, section.n);
+                    if &actual != md {
+                        mismatches.push(mismatch(None, md, actual));
+                    }
+                }
+                Vector::Mac { key, msg, mac, .. } => {
+                    let actual = kmac_kupyna(key, msg, section.n).unwrap_or_default();
+                    if &actual != mac {
+                        mismatches.push(mismatch(None, mac, actual));
+                    }
+                }
+                Vector::MonteCarlo { seed, checkpoints } => {
+                    let actual = monte_carlo_checkpoints(seed, checkpoints.len(), section.n);
+                    if let Some(k) = (0..checkpoints.len()).find(|&k| actual[k] != checkpoints[k]) {
+                        mismatches.push(mismatch(Some(k), &checkpoints[k], actual[k].clone()));
+                    }
+                }
+            }
+        }
+    }
+    mismatches
+}
+
+/// Creates a message vector from this implementation.
+///
+/// # Arguments
+///
+/// * `msg` - The message, most significant bit first.
+/// * `len` - The message length in bits.
+/// * `n` - The hash code length in bits.
+///
+/// # Returns
+///
+/// * The `Vector::Message` with its digest.
+pub fn message_vector(msg: &[u8], len: usize, n: usize) -> Vector {
+    Vector::Message {
+        len,
+        msg: msg[..len.div_ceil(8)].to_vec(),
+        md: kupyna_hash_bits(msg, len, n),
+    }
+}
+
+/// Creates a KMAC vector from this implementation.
+///
+/// # Arguments
+///
+/// * `key` - The key, `n` bits long.
+/// * `msg` - The message.
+/// * `n` - The code length in bits: 256, 384 or 512.
+///
+/// # Returns
+///
+/// * The `Vector::Mac` with its code, or `None` if KMAC does not allow the key or code length.
+pub fn kmac_vector(key: &[u8], msg: &[u8], n: usize) -> Option<Vector> {
+    Some(Vector::Mac {
+        len: msg.len() * 8,
+        key: key.to_vec(),
+        msg: msg.to_vec(),
+        mac: kmac_kupyna(key, msg, n).ok()?,
+    })
+}
+
+/// Creates a Monte Carlo vector from this implementation.
+///
+/// # Arguments
+///
+/// * `seed` - The initial seed, `n` bits long.
+/// * `count` - The number of checkpoints; CAVP files use 100.
+/// * `n` - The hash code length in bits.
+///
+/// # Returns
+///
+/// * The `Vector::MonteCarlo` with its checkpoints.
+pub fn monte_carlo_vector(seed: &[u8], count: usize, n: usize) -> Vector {
+    Vector::MonteCarlo {
+        seed: seed.to_vec(),
+        checkpoints: monte_carlo_checkpoints(seed, count, n),
+    }
+}
+
+fn monte_carlo_checkpoints(seed: &[u8], count: usize, n: usize) -> Vec<Vec<u8>> {
+    let mut seed = seed.to_vec();
+    let mut checkpoints = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut digests = [seed.clone(), seed.clone(), seed];
+        for _ in 0..MONTE_CARLO_ITERATIONS {
+            let message = digests.concat();
+            let digest = kupyna_hash_bits(&message, message.len() * 8, n);
+            digests = [digests[1].clone(), digests[2].clone(), digest];
+        }
+        let [_, _, digest] = digests;
+        checkpoints.push(digest.clone());
+        seed = digest;
+    }
+    checkpoints
+}
+
+/// Writes sections in the `.rsp` format, after a comment block.
+///
+/// # Arguments
+///
+/// * `comment` - Lines to put at the top of the file, each prefixed with `# `.
+/// * `sections` - The sections to write.
+///
+/// # Returns
+///
+/// * The file contents.
+pub fn write(comment: &str, sections: &[Section]) -> String {
+    let mut out = String::new();
+    for line in comment.lines() {
+        out.push_str(format!("# {}", line).trim_end());
+        out.push('\n');
+    }
+    for section in sections {
+        out.push_str(&format!("\n[L = {}]\n", section.n / 8));
+        for vector in &section.vectors {
+            match vector {
+                Vector::Message { len, msg, md } => {
+                    out.push_str(&format!(
+                        "\nLen = {}\nMsg = {}\nMD = {}\n",
+                        len,
+                        message_hex(msg),
+                        to_hex(md)
+                    ));
+                }
+                Vector::Mac { len, key, msg, mac } => {
+                    out.push_str(&format!(
+                        "\nLen = {}\nKey = {}\nMsg = {}\nMac = {}\n",
+                        len,
+                        to_hex(key),
+                        message_hex(msg),
+                        to_hex(mac)
+                    ));
+                }
+                Vector::MonteCarlo { seed, checkpoints } => {
+                    out.push_str(&format!("\nSeed = {}\n", to_hex(seed)));
+                    for (count, md) in checkpoints.iter().enumerate() {
+                        out.push_str(&format!("\nCOUNT = {}\nMD = {}\n", count, to_hex(md)));
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Writes a message in hex, the empty one as a single zero byte as CAVP does.
+fn message_hex(msg: &[u8]) -> String {
+    if msg.is_empty() {
+        "00".to_string()
+    } else {
+        to_hex(msg)
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err(format!("invalid hex string `{}`", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex digits in `{}`", hex))
+        })
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
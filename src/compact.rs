@@ -0,0 +1,120 @@
+//! Small-footprint implementation of the T⊕l and T+l permutations.
+//!
+//! Only the existing tables are used: the four 256-byte `SBOXES` and the 64-byte `MDS_MATRIX`,
+//! about 1.1 KiB of read-only data in all. The state is kept as a byte `Matrix`. `s_box_layer`
+//! and `rotate_rows` are done in one pass, and `mix_columns` doubles each column three times by
+//! shift-and-reduce, then XORs together the multiples selected by the bits of each MDS
+//! coefficient. No `multiply_gf` loop runs per byte.
+//!
+//! A round needs the state, the rotated copy and the 32 bytes of column multiples on the stack:
+//! under 300 bytes for the 1024-bit state, besides the input block and the returned `Vec`.
+
+use crate::t_xor_plus::{
+    block_to_matrix, matrix_to_block, plus_constant, row_shift, xor_constant, Matrix, COLS_1024,
+    COLS_512, ROWS,
+};
+use crate::tables::{MDS_MATRIX, SBOXES};
+
+/// Multiplies by x in GF(2^8), reducing by the low byte of `0x11d`.
+fn xtime(x: u8) -> u8 {
+    (x << 1) ^ ((x >> 7) * 0x1D)
+}
+
+/// Applies `s_box_layer` and `rotate_rows` in one pass.
+fn substitute_rotate<const C: usize>(state: &Matrix<C>) -> Matrix<C> {
+    let mut result = [[0u8; ROWS]; C];
+    for (col, column) in state.iter().enumerate() {
+        for (row, &byte) in column.iter().enumerate() {
+            result[(col + row_shift(row, C)) % C][row] = SBOXES[row % 4][byte as usize];
+        }
+    }
+    result
+}
+
+fn mix_column(column: &[u8; ROWS]) -> [u8; ROWS] {
+    // multiples[k][b] is row b of the column times 2^k; every coefficient is below 16
+    let mut multiples = [*column; 4];
+    for k in 1..multiples.len() {
+        multiples[k] = multiples[k - 1].map(xtime);
+    }
+
+    let mut result = [0u8; ROWS];
+    for (out, coefficients) in result.iter_mut().zip(&MDS_MATRIX) {
+        for (b, &coefficient) in coefficients.iter().enumerate() {
+            for (k, multiple) in multiples.iter().enumerate() {
+                if (coefficient >> k) & 1 == 1 {
+                    *out ^= multiple[b];
+                }
+            }
+        }
+    }
+    result
+}
+
+fn round<const C: usize>(state: &Matrix<C>) -> Matrix<C> {
+    let mut state = substitute_rotate(state);
+    for column in state.iter_mut() {
+        *column = mix_column(column);
+    }
+    state
+}
+
+fn t_xor<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_matrix::<C>(block);
+    for nu in 0..rounds {
+        for (j, column) in state.iter_mut().enumerate() {
+            column[0] ^= xor_constant(nu, j);
+        }
+        state = round(&state);
+    }
+    matrix_to_block(state)
+}
+
+fn t_plus<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_matrix::<C>(block);
+    for nu in 0..rounds {
+        for (j, column) in state.iter_mut().enumerate() {
+            *column = u64::from_le_bytes(*column)
+                .wrapping_add(plus_constant(nu, j, C))
+                .to_le_bytes();
+        }
+        state = round(&state);
+    }
+    matrix_to_block(state)
+}
+
+/// The T⊕l transformation with only the S-box and MDS tables.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds to perform.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the transformed block.
+pub fn t_xor_l(block: &[u8], rounds: usize) -> Vec<u8> {
+    if block.len() == COLS_1024 * ROWS {
+        t_xor::<COLS_1024>(block, rounds)
+    } else {
+        t_xor::<COLS_512>(block, rounds)
+    }
+}
+
+/// The T+l transformation with only the S-box and MDS tables.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds to perform.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the transformed block.
+pub fn t_plus_l(block: &[u8], rounds: usize) -> Vec<u8> {
+    if block.len() == COLS_1024 * ROWS {
+        t_plus::<COLS_1024>(block, rounds)
+    } else {
+        t_plus::<COLS_512>(block, rounds)
+    }
+}
@@ -0,0 +1,340 @@
+//! GOST 28147-89 block cipher (DSTU GOST 28147:2009).
+//!
+//! This is the legacy Ukrainian/Soviet 64-bit block cipher with a 256-bit key. It is here so that
+//! old containers can be decrypted and their contents re-protected with modern primitives. It
+//! should not be used to protect new data.
+
+use std::error::Error;
+use std::fmt;
+
+const BLOCK_SIZE: usize = 8;
+const KEY_SIZE: usize = 32;
+
+// Counter-mode constants from the standard
+const C1: u32 = 0x01010104;
+const C2: u32 = 0x01010101;
+
+// Key orders for the 32-round encryption and decryption cycles and the 16-round MAC cycle
+const ENCRYPT_SCHEDULE: [usize; 32] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 0, 1, 2, 3, 4, 5, 6, 7, 0, 1, 2, 3, 4, 5, 6, 7, 7, 6, 5, 4, 3, 2, 1, 0,
+];
+const DECRYPT_SCHEDULE: [usize; 32] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 7, 6, 5, 4, 3, 2, 1, 0, 7, 6, 5, 4, 3, 2, 1, 0, 7, 6, 5, 4, 3, 2, 1, 0,
+];
+const MAC_SCHEDULE: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 0, 1, 2, 3, 4, 5, 6, 7];
+
+/// A GOST 28147-89 substitution table. Row `k` substitutes the `k`-th nibble of the round
+/// function input, counting from the least significant one.
+pub type Sbox = [[u8; 16]; 8];
+
+/// The S-boxes from DKE order No. 1 (the "DKE 1" set), the default for Ukrainian deployments.
+pub const SBOX_DKE_1: Sbox = [
+    [
+        0xa, 0x9, 0xd, 0x6, 0xe, 0xb, 0x4, 0x5, 0xf, 0x1, 0x3, 0xc, 0x7, 0x0, 0x8, 0x2,
+    ],
+    [
+        0x8, 0x0, 0xc, 0x4, 0x9, 0x6, 0x7, 0xb, 0x2, 0x3, 0x1, 0xf, 0x5, 0xe, 0xa, 0xd,
+    ],
+    [
+        0xf, 0x6, 0x5, 0x8, 0xe, 0xb, 0xa, 0x4, 0xc, 0x0, 0x3, 0x7, 0x2, 0x9, 0x1, 0xd,
+    ],
+    [
+        0x3, 0x8, 0xd, 0x9, 0x6, 0xb, 0xf, 0x0, 0x2, 0x5, 0xc, 0xa, 0x4, 0xe, 0x1, 0x7,
+    ],
+    [
+        0xf, 0x8, 0xe, 0x9, 0x7, 0x2, 0x0, 0xd, 0xc, 0x6, 0x1, 0x5, 0xb, 0x4, 0x3, 0xa,
+    ],
+    [
+        0x2, 0x8, 0x9, 0x7, 0x5, 0xf, 0x0, 0xb, 0xc, 0x1, 0xd, 0xe, 0xa, 0x3, 0x6, 0x4,
+    ],
+    [
+        0x3, 0x8, 0xb, 0x5, 0x6, 0x4, 0xe, 0xa, 0x2, 0xc, 0x1, 0x7, 0x9, 0xf, 0xd, 0x0,
+    ],
+    [
+        0x1, 0x2, 0x3, 0xe, 0x6, 0xd, 0xb, 0x8, 0xf, 0xa, 0xc, 0x5, 0x7, 0x9, 0x0, 0x4,
+    ],
+];
+
+/// Returned when ECB input is not a whole number of 64-bit blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidLength;
+
+impl fmt::Display for InvalidLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "input length is not a multiple of {} bytes", BLOCK_SIZE)
+    }
+}
+
+impl Error for InvalidLength {}
+
+/// A GOST 28147-89 cipher instance with an expanded key and S-box.
+#[derive(Clone)]
+pub struct Gost28147 {
+    subkeys: [u32; 8],
+    // Each table substitutes one input byte (two nibbles) and places it in position
+    sbox_bytes: [[u32; 256]; 4],
+}
+
+impl Gost28147 {
+    /// Creates a cipher using the DKE 1 S-boxes.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The 256-bit key.
+    ///
+    /// # Returns
+    ///
+    /// * A `Gost28147` ready for use in any mode.
+    pub fn new(key: &[u8; KEY_SIZE]) -> Self {
+        Self::with_sbox(key, &SBOX_DKE_1)
+    }
+
+    /// Creates a cipher using a caller-supplied S-box.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The 256-bit key.
+    /// * `sbox` - The substitution table, rows ordered from the least significant nibble.
+    ///
+    /// # Returns
+    ///
+    /// * A `Gost28147` ready for use in any mode.
+    pub fn with_sbox(key: &[u8; KEY_SIZE], sbox: &Sbox) -> Self {
+        let mut subkeys = [0u32; 8];
+        for (subkey, chunk) in subkeys.iter_mut().zip(key.chunks_exact(4)) {
+            *subkey = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+
+        let mut sbox_bytes = [[0u32; 256]; 4];
+        for (position, table) in sbox_bytes.iter_mut().enumerate() {
+            for (byte, entry) in table.iter_mut().enumerate() {
+                let low = sbox[2 * position][byte & 0x0f] as u32;
+                let high = sbox[2 * position + 1][byte >> 4] as u32;
+                *entry = ((high << 4) | low) << (8 * position);
+            }
+        }
+
+        Gost28147 {
+            subkeys,
+            sbox_bytes,
+        }
+    }
+
+    /// The round function: S-box substitution followed by an 11-bit left rotation.
+    fn f(&self, x: u32) -> u32 {
+        let [b0, b1, b2, b3] = x.to_le_bytes();
+        let substituted = self.sbox_bytes[0][b0 as usize]
+            | self.sbox_bytes[1][b1 as usize]
+            | self.sbox_bytes[2][b2 as usize]
+            | self.sbox_bytes[3][b3 as usize];
+        substituted.rotate_left(11)
+    }
+
+    /// Runs the Feistel rounds over `(N1, N2)` using the given subkey order, swapping the halves
+    /// after every round.
+    fn cycle(&self, [mut n1, mut n2]: [u32; 2], schedule: &[usize]) -> [u32; 2] {
+        for &k in schedule {
+            let t = n2 ^ self.f(n1.wrapping_add(self.subkeys[k]));
+            n2 = n1;
+            n1 = t;
+        }
+        [n1, n2]
+    }
+
+    /// Encrypts a single 64-bit block (the 32-З cycle).
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The plaintext block.
+    ///
+    /// # Returns
+    ///
+    /// * The ciphertext block.
+    pub fn encrypt_block(&self, block: &[u8; BLOCK_SIZE]) -> [u8; BLOCK_SIZE] {
+        // The last round of the 32-round cycles does not swap the halves
+        let [n1, n2] = self.cycle(block_to_words(block), &ENCRYPT_SCHEDULE);
+        words_to_block([n2, n1])
+    }
+
+    /// Decrypts a single 64-bit block (the 32-Р cycle).
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The ciphertext block.
+    ///
+    /// # Returns
+    ///
+    /// * The plaintext block.
+    pub fn decrypt_block(&self, block: &[u8; BLOCK_SIZE]) -> [u8; BLOCK_SIZE] {
+        let [n1, n2] = self.cycle(block_to_words(block), &DECRYPT_SCHEDULE);
+        words_to_block([n2, n1])
+    }
+
+    /// Encrypts data in simple substitution (ECB) mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The plaintext, a whole number of 64-bit blocks.
+    ///
+    /// # Returns
+    ///
+    /// * The ciphertext, or `InvalidLength` if `data` is not block-aligned.
+    pub fn encrypt_ecb(&self, data: &[u8]) -> Result<Vec<u8>, InvalidLength> {
+        self.ecb(data, Self::encrypt_block)
+    }
+
+    /// Decrypts data in simple substitution (ECB) mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The ciphertext, a whole number of 64-bit blocks.
+    ///
+    /// # Returns
+    ///
+    /// * The plaintext, or `InvalidLength` if `data` is not block-aligned.
+    pub fn decrypt_ecb(&self, data: &[u8]) -> Result<Vec<u8>, InvalidLength> {
+        self.ecb(data, Self::decrypt_block)
+    }
+
+    fn ecb(
+        &self,
+        data: &[u8],
+        transform: fn(&Self, &[u8; BLOCK_SIZE]) -> [u8; BLOCK_SIZE],
+    ) -> Result<Vec<u8>, InvalidLength> {
+        if !data.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(InvalidLength);
+        }
+        let mut output = Vec::with_capacity(data.len());
+        for chunk in data.chunks_exact(BLOCK_SIZE) {
+            output.extend_from_slice(&transform(self, &to_block(chunk)));
+        }
+        Ok(output)
+    }
+
+    /// Encrypts or decrypts data in counter (gamma) mode. The same call performs both
+    /// directions, and the final block may be partial.
+    ///
+    /// # Arguments
+    ///
+    /// * `iv` - The 64-bit synchronisation message.
+    /// * `data` - The input of any length.
+    ///
+    /// # Returns
+    ///
+    /// * A `Vec<u8>` of the same length as `data`.
+    pub fn ctr(&self, iv: &[u8; BLOCK_SIZE], data: &[u8]) -> Vec<u8> {
+        let mut counter = block_to_words(&self.encrypt_block(iv));
+        let mut output = Vec::with_capacity(data.len());
+        for chunk in data.chunks(BLOCK_SIZE) {
+            counter = next_counter(counter);
+            let gamma = self.encrypt_block(&words_to_block(counter));
+            output.extend(chunk.iter().zip(gamma).map(|(x, g)| x ^ g));
+        }
+        output
+    }
+
+    /// Encrypts data in gamma-with-feedback (CFB) mode. The final block may be partial.
+    ///
+    /// # Arguments
+    ///
+    /// * `iv` - The 64-bit synchronisation message.
+    /// * `data` - The plaintext of any length.
+    ///
+    /// # Returns
+    ///
+    /// * A `Vec<u8>` containing the ciphertext.
+    pub fn encrypt_cfb(&self, iv: &[u8; BLOCK_SIZE], data: &[u8]) -> Vec<u8> {
+        let mut feedback = *iv;
+        let mut output = Vec::with_capacity(data.len());
+        for chunk in data.chunks(BLOCK_SIZE) {
+            let gamma = self.encrypt_block(&feedback);
+            let start = output.len();
+            output.extend(chunk.iter().zip(gamma).map(|(x, g)| x ^ g));
+            feedback[..chunk.len()].copy_from_slice(&output[start..]);
+        }
+        output
+    }
+
+    /// Decrypts data in gamma-with-feedback (CFB) mode. The final block may be partial.
+    ///
+    /// # Arguments
+    ///
+    /// * `iv` - The 64-bit synchronisation message.
+    /// * `data` - The ciphertext of any length.
+    ///
+    /// # Returns
+    ///
+    /// * A `Vec<u8>` containing the plaintext.
+    pub fn decrypt_cfb(&self, iv: &[u8; BLOCK_SIZE], data: &[u8]) -> Vec<u8> {
+        let mut feedback = *iv;
+        let mut output = Vec::with_capacity(data.len());
+        for chunk in data.chunks(BLOCK_SIZE) {
+            let gamma = self.encrypt_block(&feedback);
+            output.extend(chunk.iter().zip(gamma).map(|(x, g)| x ^ g));
+            feedback[..chunk.len()].copy_from_slice(chunk);
+        }
+        output
+    }
+
+    /// Computes the 32-bit imitovstavka (MAC) over `data`.
+    ///
+    /// A short final block is padded with zeros, and a single-block message is extended with a
+    /// zero block, since the 16-З cycle must run at least twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The message to authenticate.
+    ///
+    /// # Returns
+    ///
+    /// * The 4-byte MAC.
+    pub fn mac(&self, data: &[u8]) -> [u8; 4] {
+        let mut state = [0u32; 2];
+        let mut blocks = 0;
+        for chunk in data.chunks(BLOCK_SIZE) {
+            let [m1, m2] = block_to_words(&to_block(chunk));
+            state = self.cycle([state[0] ^ m1, state[1] ^ m2], &MAC_SCHEDULE);
+            blocks += 1;
+        }
+        for _ in blocks..2 {
+            state = self.cycle(state, &MAC_SCHEDULE);
+        }
+        state[0].to_le_bytes()
+    }
+}
+
+/// Advances the counter-mode registers: N3 modulo 2^32, N4 modulo 2^32 - 1.
+///
+/// # Arguments
+///
+/// * `counter` - The current `(N3, N4)` registers.
+///
+/// # Returns
+///
+/// * The next `(N3, N4)` registers.
+pub(crate) fn next_counter([n3, n4]: [u32; 2]) -> [u32; 2] {
+    let (sum, carry) = n4.overflowing_add(C1);
+    let n4 = if carry { sum.wrapping_add(1) } else { sum };
+    [n3.wrapping_add(C2), n4]
+}
+
+/// Copies up to one block of bytes, zero-padding a short final chunk.
+fn to_block(chunk: &[u8]) -> [u8; BLOCK_SIZE] {
+    let mut block = [0u8; BLOCK_SIZE];
+    block[..chunk.len()].copy_from_slice(chunk);
+    block
+}
+
+fn block_to_words(block: &[u8; BLOCK_SIZE]) -> [u32; 2] {
+    [
+        u32::from_le_bytes([block[0], block[1], block[2], block[3]]),
+        u32::from_le_bytes([block[4], block[5], block[6], block[7]]),
+    ]
+}
+
+fn words_to_block([n1, n2]: [u32; 2]) -> [u8; BLOCK_SIZE] {
+    let mut block = [0u8; BLOCK_SIZE];
+    block[..4].copy_from_slice(&n1.to_le_bytes());
+    block[4..].copy_from_slice(&n2.to_le_bytes());
+    block
+}
@@ -0,0 +1,84 @@
+//! Incremental hashing of messages that arrive in pieces.
+
+use crate::{compress, initial_state, output_transformation, pad_message, state_params, Backend};
+
+/// Hashes a message fed in chunks of any size, giving the same hash code as `kupyna_hash` on
+/// the whole message.
+#[derive(Debug, Clone)]
+pub struct KupynaHasher {
+    backend: Backend,
+    n: usize,
+    l: usize,
+    t: usize,
+    h: Vec<u8>,
+    /// The bytes after the last whole block, always shorter than a block.
+    buffer: Vec<u8>,
+    /// The message length so far, in bytes.
+    len: u128,
+}
+
+impl KupynaHasher {
+    /// Creates a hasher for `n`-bit hash codes using the default backend.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not a multiple of 8 from 8 to 512.
+    pub fn new(n: usize) -> Self {
+        Self::with_backend(Backend::default(), n)
+    }
+
+    /// Creates a hasher for `n`-bit hash codes using a particular backend.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not a multiple of 8 from 8 to 512.
+    pub fn with_backend(backend: Backend, n: usize) -> Self {
+        let (l, t) = state_params(n);
+        KupynaHasher {
+            backend,
+            n,
+            l,
+            t,
+            h: initial_state(l),
+            buffer: Vec::with_capacity(l / 8),
+            len: 0,
+        }
+    }
+
+    /// Appends `data` to the message, compressing every block it completes.
+    pub fn update(&mut self, mut data: &[u8]) {
+        let block_len = self.l / 8;
+        self.len += data.len() as u128;
+
+        if !self.buffer.is_empty() {
+            let take = data.len().min(block_len - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buffer.len() < block_len {
+                return;
+            }
+            self.h = compress(self.backend, &self.h, &self.buffer, self.t);
+            self.buffer.clear();
+        }
+
+        let mut blocks = data.chunks_exact(block_len);
+        for block in &mut blocks {
+            self.h = compress(self.backend, &self.h, block, self.t);
+        }
+        self.buffer.extend_from_slice(blocks.remainder());
+    }
+
+    /// Pads the message and returns its hash code.
+    pub fn finalize(self) -> Vec<u8> {
+        // The buffered tail pads the same way as the whole message, apart from the length
+        let mut padded = pad_message(&self.buffer, self.l);
+        let length_field = padded.len() - 12;
+        padded[length_field..].copy_from_slice(&(self.len * 8).to_le_bytes()[..12]);
+
+        let mut h = self.h;
+        for block in padded.chunks(self.l / 8) {
+            h = compress(self.backend, &h, block, self.t);
+        }
+        output_transformation(self.backend, &h, self.t, self.n)
+    }
+}
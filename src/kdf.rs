@@ -0,0 +1,175 @@
+//! Key derivation with HMAC-Kupyna as the pseudorandom function.
+//!
+//! `hkdf_kupyna` is HKDF (RFC 5869), for deriving keys from a secret that is already random,
+//! such as a master key. `pbkdf2_kupyna` is PBKDF2 (RFC 8018), for deriving keys from
+//! passwords, made slow by its iteration count.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::HmacKupyna;
+
+/// The HKDF extract step: a pseudorandom key of `n` bits from input keying material.
+///
+/// An empty salt is the same as the RFC's default of `n` zero bits, since HMAC pads short keys
+/// with zeros.
+///
+/// # Arguments
+///
+/// * `salt` - A non-secret random value, or empty.
+/// * `ikm` - The input keying material.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the pseudorandom key.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn hkdf_kupyna_extract(salt: &[u8], ikm: &[u8], n: usize) -> Vec<u8> {
+    let mut mac = HmacKupyna::new(salt, n);
+    mac.update(ikm);
+    mac.finalize()
+}
+
+/// The HKDF expand step: `len` bytes of output keying material from a pseudorandom key.
+///
+/// # Arguments
+///
+/// * `prk` - The pseudorandom key, usually from `hkdf_kupyna_extract`.
+/// * `info` - Context binding the output to its use, or empty.
+/// * `len` - The length of the output in bytes, at most 255 hash codes.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the output, or `InvalidOutputLength` if `len` is too long.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn hkdf_kupyna_expand(
+    prk: &[u8],
+    info: &[u8],
+    len: usize,
+    n: usize,
+) -> Result<Vec<u8>, InvalidOutputLength> {
+    // Keyed first, so a bad `n` panics rather than giving a nonsense maximum
+    let keyed = HmacKupyna::new(prk, n);
+    let max = 255 * (n / 8);
+    if len > max {
+        return Err(InvalidOutputLength { len, max });
+    }
+    let mut output = Vec::with_capacity(len);
+    let mut block = Vec::new();
+    for counter in 1..=255u8 {
+        if output.len() >= len {
+            break;
+        }
+        let mut mac = keyed.clone();
+        mac.update(&block);
+        mac.update(info);
+        mac.update(&[counter]);
+        block = mac.finalize();
+        output.extend_from_slice(&block);
+    }
+    output.truncate(len);
+    Ok(output)
+}
+
+/// Derives `len` bytes with HKDF-Kupyna: extract, then expand.
+///
+/// # Arguments
+///
+/// * `ikm` - The input keying material.
+/// * `salt` - A non-secret random value, or empty.
+/// * `info` - Context binding the output to its use, or empty.
+/// * `len` - The length of the output in bytes, at most 255 hash codes.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the output, or `InvalidOutputLength` if `len` is too long.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn hkdf_kupyna(
+    ikm: &[u8],
+    salt: &[u8],
+    info: &[u8],
+    len: usize,
+    n: usize,
+) -> Result<Vec<u8>, InvalidOutputLength> {
+    hkdf_kupyna_expand(&hkdf_kupyna_extract(salt, ikm, n), info, len, n)
+}
+
+/// Derives `len` bytes from a password with PBKDF2-HMAC-Kupyna.
+///
+/// # Arguments
+///
+/// * `password` - The password.
+/// * `salt` - A non-secret random value, unique to each password.
+/// * `iterations` - How many times to apply HMAC for each block of output.
+/// * `len` - The length of the output in bytes.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the output.
+///
+/// # Panics
+///
+/// If `iterations` is 0, or if `n` is not a multiple of 8 from 8 to 512.
+pub fn pbkdf2_kupyna(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    len: usize,
+    n: usize,
+) -> Vec<u8> {
+    assert!(iterations > 0, "PBKDF2 needs at least one iteration");
+    // Keying HMAC hashes the password, so it is done once and the keyed state copied
+    let keyed = HmacKupyna::new(password, n);
+    let mut output = Vec::with_capacity(len);
+    let mut index = 1u32;
+    while output.len() < len {
+        let mut mac = keyed.clone();
+        mac.update(salt);
+        mac.update(&index.to_be_bytes());
+        let mut u = mac.finalize();
+        let mut block = u.clone();
+        for _ in 1..iterations {
+            let mut mac = keyed.clone();
+            mac.update(&u);
+            u = mac.finalize();
+            block.iter_mut().zip(&u).for_each(|(b, u)| *b ^= u);
+        }
+        output.extend_from_slice(&block);
+        index += 1;
+    }
+    output.truncate(len);
+    output
+}
+
+/// Returned when more HKDF output is asked for than 255 hash codes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidOutputLength {
+    /// The length asked for, in bytes.
+    pub len: usize,
+    /// The longest output possible with the hash code length, in bytes.
+    pub max: usize,
+}
+
+impl fmt::Display for InvalidOutputLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "HKDF output is at most {} bytes, not {}",
+            self.max, self.len
+        )
+    }
+}
+
+impl Error for InvalidOutputLength {}
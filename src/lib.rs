@@ -0,0 +1,303 @@
+// Only the x86-64 SIMD backend needs `unsafe`, to call its `#[target_feature]` functions after
+// runtime detection. Without it, `unsafe` is forbidden outright.
+#![cfg_attr(
+    not(all(feature = "simd", target_arch = "x86_64")),
+    forbid(unsafe_code)
+)]
+#![cfg_attr(all(feature = "simd", target_arch = "x86_64"), deny(unsafe_code))]
+
+mod backend;
+#[cfg(feature = "bitsliced")]
+mod bitsliced;
+pub mod cavp;
+#[cfg(feature = "compact")]
+mod compact;
+pub mod gost;
+mod hasher;
+mod kdf;
+mod mac;
+#[cfg(feature = "table")]
+mod multi_buffer;
+pub mod permutation;
+mod pipeline;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+#[cfg(feature = "table")]
+mod t_table;
+mod t_xor_plus;
+mod tables;
+#[cfg(test)]
+mod tests;
+pub mod trace;
+
+pub use backend::{Backend, UnknownBackend};
+
+/// Internals exercised by the fuzz targets in `fuzz/`.
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub mod fuzzing {
+    pub fn pad_message(message: &[u8], l: usize) -> Vec<u8> {
+        crate::pad_message(message, l)
+    }
+}
+pub use hasher::KupynaHasher;
+pub use kdf::{
+    hkdf_kupyna, hkdf_kupyna_expand, hkdf_kupyna_extract, pbkdf2_kupyna, InvalidOutputLength,
+};
+pub use mac::{hmac_kupyna, kmac_kupyna, verify_mac, HmacKupyna, InvalidKeyLength, KmacKupyna};
+pub use pipeline::{kupyna_hash_file, kupyna_hash_reader};
+
+const L512: usize = 512;
+const L1024: usize = 1024;
+const T_512: usize = 10;
+const T_1024: usize = 14;
+
+/// Pads the input message according to the Kupyna padding scheme.
+///
+/// # Arguments
+///
+/// * `message` - A byte slice representing the message to be hashed.
+/// * `l` - The length of the blocks in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the padded message.
+fn pad_message(message: &[u8], l: usize) -> Vec<u8> {
+    pad_message_bits(message, message.len() * 8, l)
+}
+
+/// Pads a message of any number of bits according to the Kupyna padding scheme.
+///
+/// # Arguments
+///
+/// * `message` - A byte slice holding the message, most significant bit first.
+/// * `bit_len` - The length of the message in bits. Any bits beyond it are ignored.
+/// * `l` - The length of the blocks in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the padded message.
+fn pad_message_bits(message: &[u8], bit_len: usize, l: usize) -> Vec<u8> {
+    let n = bit_len;
+    // The number of zero bits, from 0 up to l - 1
+    let d = (-((n + 97) as isize)).rem_euclid(l as isize) as usize;
+    // We set the padded message size upfront to reduce allocs
+    let padded_len = (n + 1 + d + 96) / 8;
+    let mut padded_message = vec![0x00; padded_len];
+
+    // Copy the input message
+    let whole_bytes = n / 8;
+    padded_message[0..whole_bytes].copy_from_slice(&message[..whole_bytes]);
+    // Keep the leading bits of a partial last byte, and set the bit after them
+    let extra_bits = n % 8;
+    let mask = !(0xFFu8 >> extra_bits);
+    let partial = if extra_bits == 0 {
+        0
+    } else {
+        message[whole_bytes] & mask
+    };
+    padded_message[whole_bytes] = partial | (0b10000000 >> extra_bits);
+
+    // Convert the length to a byte array and copy it into the padded message
+    let n_bytes = (n as u128).to_le_bytes(); // message length in little-endian
+    padded_message[padded_len - 12..].copy_from_slice(&n_bytes[0..12]);
+
+    padded_message
+}
+
+/// Divides the padded message into blocks of length `l`.
+///
+/// # Arguments
+///
+/// * `padded_message` - A byte slice representing the padded message.
+/// * `l` - The length of the blocks in bits.
+///
+/// # Returns
+///
+/// * A `Vec<&[u8]>` containing references to the blocks.
+fn divide_into_blocks(padded_message: &[u8], l: usize) -> Vec<&[u8]> {
+    padded_message.chunks(l / 8).collect()
+}
+
+/// Truncates the block to its last `n` bits.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be truncated.
+/// * `n` - The number of bits to keep.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the truncated block.
+fn r_l_n(block: &[u8], n: usize) -> Vec<u8> {
+    block[block.len() - n / 8..].to_vec()
+}
+
+/// Picks the state size and round count for a hash code length.
+///
+/// # Arguments
+///
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * The state length `l` in bits and the number of rounds `t`.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512, which every public function taking a hash code
+/// length relies on.
+fn state_params(n: usize) -> (usize, usize) {
+    assert!(
+        n.is_multiple_of(8) && (8..=512).contains(&n),
+        "hash code length must be a multiple of 8 from 8 to 512 bits, not {n}"
+    );
+    if (8..=256).contains(&n) {
+        (L512, T_512)
+    } else {
+        (L1024, T_1024)
+    }
+}
+
+/// Computes the Kupyna hash of the input message.
+///
+/// # Arguments
+///
+/// * `message` - A byte slice representing the message to be hashed.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the hash code.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn kupyna_hash(message: &[u8], n: usize) -> Vec<u8> {
+    kupyna_hash_with(Backend::default(), message, n)
+}
+
+/// Computes the Kupyna hash of the input message using a particular backend.
+///
+/// # Arguments
+///
+/// * `backend` - The implementation of the permutations to use.
+/// * `message` - A byte slice representing the message to be hashed.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the hash code.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn kupyna_hash_with(backend: Backend, message: &[u8], n: usize) -> Vec<u8> {
+    let (l, _) = state_params(n);
+    hash_padded(backend, &pad_message(message, l), n)
+}
+
+/// Computes the Kupyna hash of a message whose length is not a whole number of bytes.
+///
+/// # Arguments
+///
+/// * `message` - A byte slice holding the message, most significant bit first.
+/// * `bit_len` - The length of the message in bits. Any bits beyond it are ignored.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the hash code.
+///
+/// # Panics
+///
+/// If `message` is shorter than `bit_len` bits, or if `n` is not a multiple of 8 from 8 to 512.
+pub fn kupyna_hash_bits(message: &[u8], bit_len: usize, n: usize) -> Vec<u8> {
+    assert!(
+        message.len() * 8 >= bit_len,
+        "message is shorter than bit_len"
+    );
+    let (l, _) = state_params(n);
+    hash_padded(
+        Backend::default(),
+        &pad_message_bits(message, bit_len, l),
+        n,
+    )
+}
+
+/// Compresses each block of a padded message, then applies the output transformation.
+fn hash_padded(backend: Backend, padded_message: &[u8], n: usize) -> Vec<u8> {
+    let (l, t) = state_params(n);
+    let blocks = divide_into_blocks(padded_message, l);
+
+    let mut h = initial_state(l);
+    for block in blocks {
+        h = compress(backend, &h, block, t);
+    }
+    output_transformation(backend, &h, t, n)
+}
+
+/// The IV is 1 << 510 (or 1 << 1023): the state size in bytes, stored in the first byte.
+fn initial_state(l: usize) -> Vec<u8> {
+    let mut h = vec![0x00; l / 8];
+    h[0] = (l / 8) as u8;
+    h
+}
+
+/// The compression function: `T⊕(h ^ m) ^ T+(m) ^ h`.
+fn compress(backend: Backend, h: &[u8], block: &[u8], t: usize) -> Vec<u8> {
+    let t_xor = backend.t_xor_l(&xor_bytes(h, block), t);
+    let t_plus = backend.t_plus_l(block, t);
+    xor_bytes(&xor_bytes(&t_xor, &t_plus), h)
+}
+
+/// The output transformation: the last `n` bits of `T⊕(h) ^ h`.
+fn output_transformation(backend: Backend, h: &[u8], t: usize, n: usize) -> Vec<u8> {
+    r_l_n(&xor_bytes(&backend.t_xor_l(h, t), h), n)
+}
+
+/// Computes the Kupyna hashes of many independent messages.
+///
+/// With the table backend, several messages are compressed together, with their permutation
+/// rounds interleaved so the table lookups for independent messages can overlap. Other backends
+/// hash the messages in turn.
+///
+/// # Arguments
+///
+/// * `messages` - The messages to be hashed.
+/// * `n` - The length of the hash codes in bits.
+///
+/// # Returns
+///
+/// * A `Vec<Vec<u8>>` with the hash code of each message, in the same order.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn kupyna_hash_many(messages: &[&[u8]], n: usize) -> Vec<Vec<u8>> {
+    // Checked here too, so an empty list panics on a bad length like any other
+    state_params(n);
+    #[cfg(feature = "table")]
+    if Backend::default() == Backend::Table {
+        return multi_buffer::hash_many(messages, n);
+    }
+
+    messages
+        .iter()
+        .map(|message| kupyna_hash(message, n))
+        .collect()
+}
+
+/// XORs two byte slices.
+///
+/// # Arguments
+///
+/// * `a` - A byte slice representing the first operand.
+/// * `b` - A byte slice representing the second operand.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the result of the XOR operation.
+fn xor_bytes(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
@@ -0,0 +1,188 @@
+//! Message authentication codes built on Kupyna.
+//!
+//! `HmacKupyna` is HMAC (RFC 2104) with Kupyna as the hash function, keyed with a key of any
+//! length, and with the state size as its block size: 64 bytes for hash codes of up to 256
+//! bits, and 128 bytes above that.
+//!
+//! `KmacKupyna` is the MAC construction of DSTU 7564: the hash of the key padded to a whole
+//! block, then the message, then the key with every bit inverted. Its key is as long as its
+//! code, 256, 384 or 512 bits.
+
+use std::error::Error;
+use std::fmt;
+use std::hint::black_box;
+
+use crate::{pad_message, state_params, KupynaHasher};
+
+/// Computes HMAC-Kupyna of a whole message.
+///
+/// # Arguments
+///
+/// * `key` - The key, of any length.
+/// * `message` - The message to authenticate.
+/// * `n` - The length of the code in bits.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the code.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn hmac_kupyna(key: &[u8], message: &[u8], n: usize) -> Vec<u8> {
+    let mut mac = HmacKupyna::new(key, n);
+    mac.update(message);
+    mac.finalize()
+}
+
+/// Computes KMAC-Kupyna of a whole message.
+///
+/// # Arguments
+///
+/// * `key` - The key, of `n` bits.
+/// * `message` - The message to authenticate.
+/// * `n` - The length of the code in bits: 256, 384 or 512.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the code, or `InvalidKeyLength` if `n` or the key's length is not
+///   one KMAC allows.
+pub fn kmac_kupyna(key: &[u8], message: &[u8], n: usize) -> Result<Vec<u8>, InvalidKeyLength> {
+    let mut mac = KmacKupyna::new(key, n)?;
+    mac.update(message);
+    Ok(mac.finalize())
+}
+
+/// Compares a computed code with an expected one in time that depends only on their lengths.
+///
+/// Comparing codes with `==` stops at the first differing byte, which tells an attacker timing
+/// the check how much of a forged code was right.
+///
+/// # Returns
+///
+/// * Whether the codes are equal.
+pub fn verify_mac(expected: &[u8], actual: &[u8]) -> bool {
+    if expected.len() != actual.len() {
+        return false;
+    }
+    let difference = expected
+        .iter()
+        .zip(actual)
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+    black_box(difference) == 0
+}
+
+/// HMAC-Kupyna of a message fed in chunks of any size.
+#[derive(Debug, Clone)]
+pub struct HmacKupyna {
+    inner: KupynaHasher,
+    /// The outer hash, already fed the key XORed with the outer pad.
+    outer: KupynaHasher,
+}
+
+impl HmacKupyna {
+    /// Creates an HMAC for `n`-bit codes. Keys longer than a block are hashed first.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not a multiple of 8 from 8 to 512.
+    pub fn new(key: &[u8], n: usize) -> Self {
+        let block_len = state_params(n).0 / 8;
+        let mut block = if key.len() > block_len {
+            let mut hasher = KupynaHasher::new(n);
+            hasher.update(key);
+            hasher.finalize()
+        } else {
+            key.to_vec()
+        };
+        block.resize(block_len, 0);
+
+        let mut inner = KupynaHasher::new(n);
+        let mut outer = KupynaHasher::new(n);
+        inner.update(&block.iter().map(|b| b ^ 0x36).collect::<Vec<_>>());
+        outer.update(&block.iter().map(|b| b ^ 0x5c).collect::<Vec<_>>());
+        HmacKupyna { inner, outer }
+    }
+
+    /// Appends `data` to the message.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Returns the code.
+    pub fn finalize(self) -> Vec<u8> {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+}
+
+/// KMAC-Kupyna of a message fed in chunks of any size.
+#[derive(Debug, Clone)]
+pub struct KmacKupyna {
+    /// Already fed the padded key.
+    hasher: KupynaHasher,
+    key: Vec<u8>,
+}
+
+impl KmacKupyna {
+    /// Creates a KMAC for `n`-bit codes.
+    ///
+    /// # Returns
+    ///
+    /// * The KMAC, or `InvalidKeyLength` if `n` is not 256, 384 or 512, or the key is not `n`
+    ///   bits long.
+    pub fn new(key: &[u8], n: usize) -> Result<Self, InvalidKeyLength> {
+        if ![256, 384, 512].contains(&n) || key.len() * 8 != n {
+            return Err(InvalidKeyLength {
+                n,
+                key_len: key.len(),
+            });
+        }
+        let mut hasher = KupynaHasher::new(n);
+        hasher.update(&pad_message(key, state_params(n).0));
+        Ok(KmacKupyna {
+            hasher,
+            key: key.to_vec(),
+        })
+    }
+
+    /// Appends `data` to the message.
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    /// Returns the code.
+    pub fn finalize(self) -> Vec<u8> {
+        let mut hasher = self.hasher;
+        hasher.update(&self.key.iter().map(|b| !b).collect::<Vec<_>>());
+        hasher.finalize()
+    }
+}
+
+/// Returned when a KMAC code length or key length is not one the standard defines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidKeyLength {
+    /// The code length asked for, in bits.
+    pub n: usize,
+    /// The key length given, in bytes.
+    pub key_len: usize,
+}
+
+impl fmt::Display for InvalidKeyLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if [256, 384, 512].contains(&self.n) {
+            write!(
+                f,
+                "KMAC-{} needs a {}-byte key, not {} bytes",
+                self.n,
+                self.n / 8,
+                self.key_len
+            )
+        } else {
+            write!(f, "KMAC codes are 256, 384 or 512 bits, not {}", self.n)
+        }
+    }
+}
+
+impl Error for InvalidKeyLength {}
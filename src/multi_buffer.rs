@@ -0,0 +1,120 @@
+//! Multi-buffer hashing of many independent messages.
+//!
+//! Up to `LANES` messages are in flight at once. Each step compresses one block of every
+//! message, or runs its output transformation once the blocks are used up, with all their T⊕l
+//! and T+l permutations going through `t_table::permute_lanes` together. A lane that finishes
+//! its message is refilled with the next one, so long messages do not hold up short ones.
+
+use crate::t_table::permute_lanes;
+use crate::t_xor_plus::{block_to_words, words_to_block, COLS_1024, COLS_512, ROWS};
+use crate::{pad_message, r_l_n, state_params, L512};
+
+const LANES: usize = 4;
+
+/// A message being hashed in one lane.
+struct Lane<const C: usize> {
+    index: usize,
+    padded_message: Vec<u8>,
+    offset: usize,
+    h: [u64; C],
+}
+
+impl<const C: usize> Lane<C> {
+    fn new(index: usize, message: &[u8]) -> Self {
+        // The IV is the state size in bytes, stored in the first byte
+        let mut h = [0u64; C];
+        h[0] = (C * ROWS) as u64;
+        Lane {
+            index,
+            padded_message: pad_message(message, C * ROWS * 8),
+            offset: 0,
+            h,
+        }
+    }
+
+    /// Whether every block has been compressed, leaving only the output transformation.
+    fn is_finishing(&self) -> bool {
+        self.offset == self.padded_message.len()
+    }
+
+    fn next_block(&self) -> [u64; C] {
+        block_to_words(&self.padded_message[self.offset..self.offset + C * ROWS])
+    }
+}
+
+/// Computes the Kupyna hashes of many messages, interleaving their compression.
+///
+/// # Arguments
+///
+/// * `messages` - The messages to be hashed.
+/// * `n` - The length of the hash codes in bits.
+///
+/// # Returns
+///
+/// * A `Vec<Vec<u8>>` with the hash code of each message, in the same order.
+pub(crate) fn hash_many(messages: &[&[u8]], n: usize) -> Vec<Vec<u8>> {
+    let (l, t) = state_params(n);
+    if l == L512 {
+        hash_many_with::<COLS_512>(messages, n, t)
+    } else {
+        hash_many_with::<COLS_1024>(messages, n, t)
+    }
+}
+
+fn hash_many_with<const C: usize>(messages: &[&[u8]], n: usize, rounds: usize) -> Vec<Vec<u8>> {
+    let mut digests = vec![Vec::new(); messages.len()];
+    let mut pending = messages.iter().enumerate();
+    let mut lanes: [Option<Lane<C>>; LANES] = std::array::from_fn(|_| None);
+
+    loop {
+        for lane in lanes.iter_mut().filter(|lane| lane.is_none()) {
+            *lane = pending
+                .next()
+                .map(|(index, message)| Lane::new(index, message));
+        }
+        if lanes.iter().all(Option::is_none) {
+            break;
+        }
+
+        // Idle lanes, and the T+ half of a finishing lane, run on zero states and are ignored
+        let mut xor_states = [[0u64; C]; LANES];
+        let mut plus_states = [[0u64; C]; LANES];
+        for (lane, (xor_state, plus_state)) in lanes
+            .iter()
+            .zip(xor_states.iter_mut().zip(plus_states.iter_mut()))
+        {
+            match lane {
+                Some(lane) if lane.is_finishing() => *xor_state = lane.h,
+                Some(lane) => {
+                    let m = lane.next_block();
+                    *xor_state = xor_words(&lane.h, &m);
+                    *plus_state = m;
+                }
+                None => {}
+            }
+        }
+
+        permute_lanes(&mut xor_states, &mut plus_states, rounds);
+
+        for (slot, (xor_state, plus_state)) in lanes
+            .iter_mut()
+            .zip(xor_states.iter().zip(plus_states.iter()))
+        {
+            let Some(lane) = slot else { continue };
+            if lane.is_finishing() {
+                lane.h = xor_words(&lane.h, xor_state);
+                digests[lane.index] = r_l_n(&words_to_block(lane.h), n);
+                *slot = None;
+            } else {
+                lane.h = xor_words(&lane.h, &xor_words(xor_state, plus_state));
+                lane.offset += C * ROWS;
+            }
+        }
+    }
+
+    digests
+}
+
+fn xor_words<const C: usize>(a: &[u64; C], b: &[u64; C]) -> [u64; C] {
+    std::array::from_fn(|j| a[j] ^ b[j])
+}
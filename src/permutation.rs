@@ -0,0 +1,153 @@
+//! The T⊕l and T+l permutations over typed 512-bit and 1024-bit states.
+//!
+//! These are the permutations inside the Kupyna compression function, exposed for building other
+//! permutation-based constructions and for research. A `State` is changed in place, either a
+//! whole permutation at a time or one round at a time, with any number of rounds up to the
+//! standard's 10 or 14. Every round and permutation can also be inverted.
+
+use crate::t_xor_plus::{
+    add_constant_plus, add_constant_xor, block_to_matrix, inverse_add_constant_plus,
+    inverse_mix_columns, inverse_rotate_rows, inverse_s_box_layer, matrix_to_block, mix_columns,
+    plus_constant, rotate_rows, s_box_layer, t_plus_inverse_l, t_xor_inverse_l, xor_constant,
+    Matrix, COLS_1024, COLS_512, ROWS,
+};
+use crate::{Backend, T_1024, T_512};
+
+/// A Kupyna state of `C` columns of 8 bytes. Only the 512-bit and 1024-bit sizes can be built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct State<const C: usize>(Matrix<C>);
+
+/// The 512-bit state, used for hash codes of up to 256 bits.
+pub type State512 = State<COLS_512>;
+
+/// The 1024-bit state, used for hash codes of more than 256 bits.
+pub type State1024 = State<COLS_1024>;
+
+impl State<COLS_512> {
+    /// Creates a state from its 64 bytes, column by column.
+    pub fn new(bytes: [u8; COLS_512 * ROWS]) -> Self {
+        State(block_to_matrix(&bytes))
+    }
+}
+
+impl State<COLS_1024> {
+    /// Creates a state from its 128 bytes, column by column.
+    pub fn new(bytes: [u8; COLS_1024 * ROWS]) -> Self {
+        State(block_to_matrix(&bytes))
+    }
+}
+
+impl<const C: usize> State<C> {
+    /// The number of rounds the standard uses for this state size.
+    pub const ROUNDS: usize = if C == COLS_1024 { T_1024 } else { T_512 };
+
+    /// The state's bytes, column by column.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        matrix_to_block(self.0)
+    }
+
+    /// The state as columns, so `columns()[j][i]` is row `i` of column `j`.
+    pub fn columns(&self) -> &[[u8; ROWS]; C] {
+        &self.0
+    }
+
+    /// The T⊕l round constant XORed into row 0 of a column.
+    ///
+    /// # Arguments
+    ///
+    /// * `round` - The round number, counting from 0.
+    /// * `column` - The column index.
+    ///
+    /// # Returns
+    ///
+    /// * The constant byte.
+    pub fn xor_constant(round: usize, column: usize) -> u8 {
+        xor_constant(round, column)
+    }
+
+    /// The T+l round constant added to a column, as a little-endian integer with row 0 in the
+    /// low byte.
+    ///
+    /// # Arguments
+    ///
+    /// * `round` - The round number, counting from 0.
+    /// * `column` - The column index.
+    ///
+    /// # Returns
+    ///
+    /// * The constant, added modulo 2^64.
+    pub fn plus_constant(round: usize, column: usize) -> u64 {
+        plus_constant(round, column, C)
+    }
+
+    /// Applies round `round` of T⊕l: `add_constant_xor`, `s_box_layer`, `rotate_rows` and
+    /// `mix_columns`.
+    pub fn xor_round(&mut self, round: usize) {
+        self.0 = mix_columns(rotate_rows(s_box_layer(add_constant_xor(self.0, round))));
+    }
+
+    /// Applies round `round` of T+l: `add_constant_plus`, `s_box_layer`, `rotate_rows` and
+    /// `mix_columns`.
+    pub fn plus_round(&mut self, round: usize) {
+        self.0 = mix_columns(rotate_rows(s_box_layer(add_constant_plus(self.0, round))));
+    }
+
+    /// Undoes round `round` of T⊕l, applying the inverses of its steps in reverse order.
+    pub fn inverse_xor_round(&mut self, round: usize) {
+        let state = inverse_s_box_layer(inverse_rotate_rows(inverse_mix_columns(self.0)));
+        // XORing the constant again undoes it
+        self.0 = add_constant_xor(state, round);
+    }
+
+    /// Undoes round `round` of T+l, applying the inverses of its steps in reverse order.
+    pub fn inverse_plus_round(&mut self, round: usize) {
+        let state = inverse_s_box_layer(inverse_rotate_rows(inverse_mix_columns(self.0)));
+        self.0 = inverse_add_constant_plus(state, round);
+    }
+
+    /// Applies rounds `0..rounds` of T⊕l with the default backend.
+    ///
+    /// # Panics
+    ///
+    /// If `rounds` is not between 1 and `ROUNDS`.
+    pub fn t_xor(&mut self, rounds: usize) {
+        self.permute(rounds, Backend::t_xor_l);
+    }
+
+    /// Applies rounds `0..rounds` of T+l with the default backend.
+    ///
+    /// # Panics
+    ///
+    /// If `rounds` is not between 1 and `ROUNDS`.
+    pub fn t_plus(&mut self, rounds: usize) {
+        self.permute(rounds, Backend::t_plus_l);
+    }
+
+    /// Undoes `t_xor(rounds)`.
+    ///
+    /// # Panics
+    ///
+    /// If `rounds` is not between 1 and `ROUNDS`.
+    pub fn t_xor_inverse(&mut self, rounds: usize) {
+        self.permute(rounds, |_, block, rounds| t_xor_inverse_l(block, rounds));
+    }
+
+    /// Undoes `t_plus(rounds)`.
+    ///
+    /// # Panics
+    ///
+    /// If `rounds` is not between 1 and `ROUNDS`.
+    pub fn t_plus_inverse(&mut self, rounds: usize) {
+        self.permute(rounds, |_, block, rounds| t_plus_inverse_l(block, rounds));
+    }
+
+    fn permute(&mut self, rounds: usize, permutation: fn(Backend, &[u8], usize) -> Vec<u8>) {
+        assert!(
+            (1..=Self::ROUNDS).contains(&rounds),
+            "rounds must be between 1 and {}",
+            Self::ROUNDS
+        );
+        let bytes = permutation(Backend::default(), &self.to_bytes(), rounds);
+        self.0 = block_to_matrix(&bytes);
+    }
+}
@@ -0,0 +1,114 @@
+//! Hashing files with reading and compression overlapped.
+//!
+//! A reader thread fills one buffer while the calling thread compresses the other, and they
+//! swap when both are done. As long as neither side is idle, hashing takes about as long as the
+//! slower of the I/O and the compression, rather than both one after the other.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+use crate::KupynaHasher;
+
+/// The size of each of the two buffers, a whole number of blocks of either state size.
+const BUFFER_LEN: usize = 1 << 20;
+
+/// A filled buffer with the length of its data, or the error that ended reading.
+type Filled = io::Result<(Vec<u8>, usize)>;
+
+/// Computes the Kupyna hash of everything a reader yields, reading ahead on another thread.
+///
+/// # Arguments
+///
+/// * `reader` - The data to hash.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * The hash code, or the first error from `reader`.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn kupyna_hash_reader<R: Read + Send>(reader: R, n: usize) -> io::Result<Vec<u8>> {
+    hash_reader_with(reader, n, BUFFER_LEN)
+}
+
+/// Computes the Kupyna hash of a file, reading ahead on another thread.
+///
+/// # Arguments
+///
+/// * `path` - The file to hash.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * The hash code, or the error from opening or reading the file.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn kupyna_hash_file<P: AsRef<Path>>(path: P, n: usize) -> io::Result<Vec<u8>> {
+    kupyna_hash_reader(File::open(path)?, n)
+}
+
+pub(crate) fn hash_reader_with<R: Read + Send>(
+    reader: R,
+    n: usize,
+    buffer_len: usize,
+) -> io::Result<Vec<u8>> {
+    let (full_sender, full) = mpsc::sync_channel(2);
+    let (empty, empty_receiver) = mpsc::sync_channel(2);
+    for _ in 0..2 {
+        empty.send(vec![0; buffer_len]).unwrap();
+    }
+
+    // Moving the channel ends in means returning early drops them, which stops the reader
+    // Created first, so a bad length panics before anything is read
+    let mut hasher = KupynaHasher::new(n);
+    thread::scope(move |scope| {
+        scope.spawn(move || read_ahead(reader, empty_receiver, full_sender));
+        for filled in full {
+            let (buffer, len) = filled?;
+            hasher.update(&buffer[..len]);
+            // The reader has stopped if this fails, and the next receive ends the loop
+            let _ = empty.send(buffer);
+        }
+        Ok(hasher.finalize())
+    })
+}
+
+/// Fills each empty buffer from `reader` and sends it back, until the end of the data.
+fn read_ahead<R: Read>(mut reader: R, empty: Receiver<Vec<u8>>, full: SyncSender<Filled>) {
+    while let Ok(mut buffer) = empty.recv() {
+        match fill(&mut reader, &mut buffer) {
+            Ok(0) => return,
+            Ok(len) => {
+                let end = len < buffer.len();
+                if full.send(Ok((buffer, len))).is_err() || end {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = full.send(Err(e));
+                return;
+            }
+        }
+    }
+}
+
+/// Reads until `buffer` is full or the data ends, returning how much was read.
+fn fill<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
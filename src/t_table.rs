@@ -0,0 +1,157 @@
+//! T-table implementation of the T⊕l and T+l permutations.
+//!
+//! Each state column is held as a little-endian `u64` (row 0 in the low byte). `s_box_layer`,
+//! `rotate_rows` and `mix_columns` are folded into eight tables, one per row, mapping an input
+//! byte to its contribution to the whole output column. A round then costs eight lookups and
+//! XORs per column.
+
+use crate::t_xor_plus::{
+    block_to_words, multiply_gf, row_shift, words_to_block, COLS_1024, COLS_512, ROWS,
+};
+use crate::tables::{MDS_MATRIX, SBOXES};
+
+/// `TABLES[b][x]` is the output column produced by byte `x` in row `b` of the input column.
+static TABLES: [[u64; 256]; ROWS] = build_tables();
+
+const fn build_tables() -> [[u64; 256]; ROWS] {
+    let mut tables = [[0u64; 256]; ROWS];
+    let mut b = 0;
+    while b < ROWS {
+        let mut x = 0;
+        while x < 256 {
+            let s = SBOXES[b % 4][x];
+            let mut entry = 0u64;
+            let mut row = 0;
+            while row < ROWS {
+                entry |= (multiply_gf(s, MDS_MATRIX[row][b]) as u64) << (8 * row);
+                row += 1;
+            }
+            tables[b][x] = entry;
+            x += 1;
+        }
+        b += 1;
+    }
+    tables
+}
+
+/// Applies `s_box_layer`, `rotate_rows` and `mix_columns` in one pass.
+fn substitute_rotate_mix<const C: usize>(state: &[u64; C]) -> [u64; C] {
+    let mut result = [0u64; C];
+    for (j, column) in result.iter_mut().enumerate() {
+        for (b, table) in TABLES.iter().enumerate() {
+            // Row b of output column j comes from input column j - shift
+            let source = state[(j + C - row_shift(b, C)) % C];
+            *column ^= table[(source >> (8 * b)) as u8 as usize];
+        }
+    }
+    result
+}
+
+/// Row by row, applies `substitute_rotate_mix` to several states at once, so the lookups of
+/// independent states can overlap.
+fn substitute_rotate_mix_lanes<const C: usize, const N: usize>(
+    states: &[[u64; C]; N],
+) -> [[u64; C]; N] {
+    let mut result = [[0u64; C]; N];
+    for j in 0..C {
+        for (b, table) in TABLES.iter().enumerate() {
+            let source = (j + C - row_shift(b, C)) % C;
+            for (state, out) in states.iter().zip(result.iter_mut()) {
+                out[j] ^= table[(state[source] >> (8 * b)) as u8 as usize];
+            }
+        }
+    }
+    result
+}
+
+fn add_constant_xor<const C: usize>(state: &mut [u64; C], round: usize) {
+    for (j, column) in state.iter_mut().enumerate() {
+        *column ^= ((j * 0x10) ^ round) as u64;
+    }
+}
+
+fn add_constant_plus<const C: usize>(state: &mut [u64; C], round: usize) {
+    for (j, column) in state.iter_mut().enumerate() {
+        *column = column
+            .wrapping_add(0x00F0F0F0F0F0F0F3u64 ^ (((((C - j - 1) * 0x10) ^ round) as u64) << 56));
+    }
+}
+
+fn t_xor<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_words::<C>(block);
+    for nu in 0..rounds {
+        add_constant_xor(&mut state, nu);
+        state = substitute_rotate_mix(&state);
+    }
+    words_to_block(state)
+}
+
+fn t_plus<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_words::<C>(block);
+    for nu in 0..rounds {
+        add_constant_plus(&mut state, nu);
+        state = substitute_rotate_mix(&state);
+    }
+    words_to_block(state)
+}
+
+/// Runs T⊕l over `xor_states` and T+l over `plus_states`, interleaving all of them round by
+/// round.
+///
+/// # Arguments
+///
+/// * `xor_states` - The states to transform with T⊕l, as column words.
+/// * `plus_states` - The states to transform with T+l, as column words.
+/// * `rounds` - The number of rounds to perform.
+pub(crate) fn permute_lanes<const C: usize, const N: usize>(
+    xor_states: &mut [[u64; C]; N],
+    plus_states: &mut [[u64; C]; N],
+    rounds: usize,
+) {
+    for nu in 0..rounds {
+        for state in xor_states.iter_mut() {
+            add_constant_xor(state, nu);
+        }
+        for state in plus_states.iter_mut() {
+            add_constant_plus(state, nu);
+        }
+        *xor_states = substitute_rotate_mix_lanes(xor_states);
+        *plus_states = substitute_rotate_mix_lanes(plus_states);
+    }
+}
+
+/// The T⊕l transformation using lookup tables.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds to perform.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the transformed block.
+pub fn t_xor_l(block: &[u8], rounds: usize) -> Vec<u8> {
+    if block.len() == COLS_1024 * ROWS {
+        t_xor::<COLS_1024>(block, rounds)
+    } else {
+        t_xor::<COLS_512>(block, rounds)
+    }
+}
+
+/// The T+l transformation using lookup tables.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds to perform.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the transformed block.
+pub fn t_plus_l(block: &[u8], rounds: usize) -> Vec<u8> {
+    if block.len() == COLS_1024 * ROWS {
+        t_plus::<COLS_1024>(block, rounds)
+    } else {
+        t_plus::<COLS_512>(block, rounds)
+    }
+}
@@ -1,89 +1,104 @@
-const ROWS: usize = 8;
-const COLS: usize = 8; // For 512-bit state, adjust if needed
+pub(crate) const ROWS: usize = 8;
+pub(crate) const COLS_512: usize = 8;
+pub(crate) const COLS_1024: usize = 16;
 
 const BITS_IN_BYTE: u8 = 8;
 const REDUCTION_POLYNOMIAL: u16 = 0x011d;
 
-type Matrix = [[u8; COLS]; ROWS];
+/// The state as `C` columns of `ROWS` bytes, so `state[j][i]` is row `i` of column `j`.
+pub(crate) type Matrix<const C: usize> = [[u8; ROWS]; C];
 
-use crate::tables::{MDS_MATRIX, SBOXES};
+use crate::tables::{INVERSE_MDS_MATRIX, INVERSE_SBOXES, MDS_MATRIX, SBOXES};
 
-const TRANSFORM_VECTOR: [u8; 8] = [0x01, 0x01, 0x05, 0x01, 0x08, 0x06, 0x07, 0x04];
-
-pub(crate) fn block_to_matrix(block: &[u8]) -> Matrix {
-    let mut matrix = [[0u8; COLS]; ROWS];
-    for i in 0..ROWS {
-        for j in 0..COLS {
-            matrix[i][j] = block[i * COLS + j];
-        }
+pub(crate) fn block_to_matrix<const C: usize>(block: &[u8]) -> Matrix<C> {
+    let mut matrix = [[0u8; ROWS]; C];
+    for (j, column) in matrix.iter_mut().enumerate() {
+        column.copy_from_slice(&block[j * ROWS..(j + 1) * ROWS]);
     }
     matrix
 }
 
-fn matrix_to_block(matrix: Matrix) -> Vec<u8> {
-    let mut block = vec![0u8; ROWS * COLS];
-    for i in 0..ROWS {
-        for j in 0..COLS {
-            block[i * COLS + j] = matrix[i][j];
-        }
-    }
-    block
+pub(crate) fn matrix_to_block<const C: usize>(matrix: Matrix<C>) -> Vec<u8> {
+    matrix.concat()
 }
 
-pub(crate) fn add_constant_xor(mut state: Matrix, round: usize) -> Matrix {
-    for j in 0..COLS {
-        let constant = ((j * 0x10) ^ round) as u8;
-        state[j][0] ^= constant;
+/// Loads the state as little-endian column words, row 0 in the low byte.
+#[cfg(any(feature = "table", feature = "bitsliced"))]
+pub(crate) fn block_to_words<const C: usize>(block: &[u8]) -> [u64; C] {
+    let mut words = [0u64; C];
+    for (word, chunk) in words.iter_mut().zip(block.chunks_exact(ROWS)) {
+        *word = u64::from_le_bytes(chunk.try_into().unwrap());
     }
-    state
+    words
 }
 
-pub(crate) fn add_constant_plus(mut state: Matrix, round: usize) -> Matrix {
-    let state_ptr = state.as_mut_ptr() as *mut u64;
+#[cfg(any(feature = "table", feature = "bitsliced"))]
+pub(crate) fn words_to_block<const C: usize>(words: [u64; C]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
 
-    for j in 0..COLS {
-        unsafe {
-            *state_ptr.add(j) = state_ptr.add(j).read().wrapping_add(
-                0x00F0F0F0F0F0F0F3u64 ^ (((((COLS - j - 1) * 0x10) ^ round) as u64) << 56),
-            );
-        }
+/// The T⊕l round constant XORed into row 0 of column `column` in round `round`.
+pub(crate) const fn xor_constant(round: usize, column: usize) -> u8 {
+    ((column * 0x10) ^ round) as u8
+}
+
+/// The T+l round constant added to column `column` of a `cols`-column state in round `round`,
+/// as a little-endian 64-bit integer with row 0 in the low byte.
+pub(crate) const fn plus_constant(round: usize, column: usize, cols: usize) -> u64 {
+    0x00F0F0F0F0F0F0F3u64 ^ (((((cols - column - 1) * 0x10) ^ round) as u64) << 56)
+}
+
+pub(crate) fn add_constant_xor<const C: usize>(mut state: Matrix<C>, round: usize) -> Matrix<C> {
+    for (j, column) in state.iter_mut().enumerate() {
+        column[0] ^= xor_constant(round, j);
     }
+    state
+}
 
+pub(crate) fn add_constant_plus<const C: usize>(mut state: Matrix<C>, round: usize) -> Matrix<C> {
+    // Each column is added as a little-endian 64-bit integer, row 0 being the low byte
+    for (j, column) in state.iter_mut().enumerate() {
+        *column = u64::from_le_bytes(*column)
+            .wrapping_add(plus_constant(round, j, C))
+            .to_le_bytes();
+    }
     state
 }
 
-pub(crate) fn s_box_layer(mut state: Matrix) -> Matrix {
-    for i in 0..ROWS {
-        for j in 0..COLS {
-            state[j][i] = SBOXES[i % 4][state[j][i] as usize];
+pub(crate) fn s_box_layer<const C: usize>(mut state: Matrix<C>) -> Matrix<C> {
+    for column in state.iter_mut() {
+        for (i, byte) in column.iter_mut().enumerate() {
+            *byte = SBOXES[i % 4][*byte as usize];
         }
     }
     state
 }
 
-pub(crate) fn rotate_rows(mut state: Matrix) -> Matrix {
-    let mut temp = [0u8; COLS];
-    let mut shift: i32 = -1;
-    for i in 0..ROWS {
-        if (i == ROWS - 1) && false {
-            shift = 11;
-        } else {
-            shift += 1;
-        }
-        for col in 0..8 {
-            temp[(col + shift as usize) % 8] = state[col][i];
-        }
-        for col in 0..8 {
-            state[col][i] = temp[col];
+/// The number of columns row `row` is rotated by. Every row moves by its index, except the last
+/// row of the 1024-bit state, which moves by 11.
+pub(crate) const fn row_shift(row: usize, cols: usize) -> usize {
+    if cols == COLS_1024 && row == ROWS - 1 {
+        11
+    } else {
+        row
+    }
+}
+
+pub(crate) fn rotate_rows<const C: usize>(state: Matrix<C>) -> Matrix<C> {
+    let mut result = [[0u8; ROWS]; C];
+    for (col, column) in state.iter().enumerate() {
+        for (row, &byte) in column.iter().enumerate() {
+            result[(col + row_shift(row, C)) % C][row] = byte;
         }
     }
-    state
+    result
 }
 
-fn multiply_gf(mut x: u8, mut y: u8) -> u8 {
+pub(crate) const fn multiply_gf(mut x: u8, mut y: u8) -> u8 {
     let mut r = 0u8;
 
-    for _ in 0..BITS_IN_BYTE {
+    let mut i = 0;
+    while i < BITS_IN_BYTE {
         if y & 1 == 1 {
             r ^= x;
         }
@@ -93,65 +108,187 @@ fn multiply_gf(mut x: u8, mut y: u8) -> u8 {
             x ^= REDUCTION_POLYNOMIAL as u8;
         }
         y >>= 1;
+        i += 1;
     }
 
     r
 }
 
-pub(crate) fn mix_columns(mut state: Matrix) -> Matrix {
-    let mut result = [[0u8; COLS]; ROWS];
+pub(crate) fn mix_columns<const C: usize>(state: Matrix<C>) -> Matrix<C> {
+    let mut result = [[0u8; ROWS]; C];
 
-    for col in 0..COLS {
-        for row in (0..ROWS).rev() {
+    for (column, result_column) in state.iter().zip(result.iter_mut()) {
+        for (row, result_byte) in result_column.iter_mut().enumerate().rev() {
             let mut product = 0u8;
             for b in (0..ROWS).rev() {
-                product ^= multiply_gf(state[col][b], MDS_MATRIX[row][b]);
+                product ^= multiply_gf(column[b], MDS_MATRIX[row][b]);
             }
-            result[col][row] = product;
+            *result_byte = product;
         }
     }
 
     result
 }
 
-/// Placeholder for the T⊕l transformation.
+pub(crate) fn inverse_add_constant_plus<const C: usize>(
+    mut state: Matrix<C>,
+    round: usize,
+) -> Matrix<C> {
+    for (j, column) in state.iter_mut().enumerate() {
+        *column = u64::from_le_bytes(*column)
+            .wrapping_sub(plus_constant(round, j, C))
+            .to_le_bytes();
+    }
+    state
+}
+
+pub(crate) fn inverse_s_box_layer<const C: usize>(mut state: Matrix<C>) -> Matrix<C> {
+    for column in state.iter_mut() {
+        for (i, byte) in column.iter_mut().enumerate() {
+            *byte = INVERSE_SBOXES[i % 4][*byte as usize];
+        }
+    }
+    state
+}
+
+pub(crate) fn inverse_rotate_rows<const C: usize>(state: Matrix<C>) -> Matrix<C> {
+    let mut result = [[0u8; ROWS]; C];
+    for (col, column) in result.iter_mut().enumerate() {
+        for (row, byte) in column.iter_mut().enumerate() {
+            *byte = state[(col + row_shift(row, C)) % C][row];
+        }
+    }
+    result
+}
+
+pub(crate) fn inverse_mix_columns<const C: usize>(state: Matrix<C>) -> Matrix<C> {
+    let mut result = [[0u8; ROWS]; C];
+    for (column, result_column) in state.iter().zip(result.iter_mut()) {
+        for (row, result_byte) in result_column.iter_mut().enumerate() {
+            *result_byte = column
+                .iter()
+                .zip(INVERSE_MDS_MATRIX[row])
+                .fold(0, |product, (&byte, coefficient)| {
+                    product ^ multiply_gf(byte, coefficient)
+                });
+        }
+    }
+    result
+}
+
+fn t_xor<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_matrix::<C>(block);
+    for nu in 0..rounds {
+        state = add_constant_xor(state, nu);
+        state = s_box_layer(state);
+        state = rotate_rows(state);
+        state = mix_columns(state);
+    }
+    matrix_to_block(state)
+}
+
+fn t_plus<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_matrix::<C>(block);
+    for nu in 0..rounds {
+        state = add_constant_plus(state, nu);
+        state = s_box_layer(state);
+        state = rotate_rows(state);
+        state = mix_columns(state);
+    }
+    matrix_to_block(state)
+}
+
+fn t_xor_inverse<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_matrix::<C>(block);
+    for nu in (0..rounds).rev() {
+        state = inverse_mix_columns(state);
+        state = inverse_rotate_rows(state);
+        state = inverse_s_box_layer(state);
+        // XORing the constant again undoes it
+        state = add_constant_xor(state, nu);
+    }
+    matrix_to_block(state)
+}
+
+fn t_plus_inverse<const C: usize>(block: &[u8], rounds: usize) -> Vec<u8> {
+    let mut state = block_to_matrix::<C>(block);
+    for nu in (0..rounds).rev() {
+        state = inverse_mix_columns(state);
+        state = inverse_rotate_rows(state);
+        state = inverse_s_box_layer(state);
+        state = inverse_add_constant_plus(state, nu);
+    }
+    matrix_to_block(state)
+}
+
+/// The T⊕l transformation.
 ///
 /// # Arguments
 ///
-/// * `block` - A byte slice representing the block to be transformed.
-/// * `_rounds` - The number of rounds to perform.
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds to perform.
 ///
 /// # Returns
 ///
 /// * A `Vec<u8>` containing the transformed block.
 pub fn t_xor_l(block: &[u8], rounds: usize) -> Vec<u8> {
-    let mut state = block_to_matrix(block);
-    for nu in 0..rounds {
-        state = add_constant_xor(state, nu);
-        state = s_box_layer(state);
-        state = rotate_rows(state);
-        state = mix_columns(state);
+    if block.len() == COLS_1024 * ROWS {
+        t_xor::<COLS_1024>(block, rounds)
+    } else {
+        t_xor::<COLS_512>(block, rounds)
     }
-    matrix_to_block(state)
 }
 
-/// Placeholder for the T+l transformation.
+/// The T+l transformation.
 ///
 /// # Arguments
 ///
-/// * `block` - A byte slice representing the block to be transformed.
-/// * `_rounds` - The number of rounds to perform.
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds to perform.
 ///
 /// # Returns
 ///
 /// * A `Vec<u8>` containing the transformed block.
 pub fn t_plus_l(block: &[u8], rounds: usize) -> Vec<u8> {
-    let mut state = block_to_matrix(block);
-    for nu in 0..rounds {
-        state = add_constant_plus(state, nu);
-        state = s_box_layer(state);
-        state = rotate_rows(state);
-        state = mix_columns(state);
+    if block.len() == COLS_1024 * ROWS {
+        t_plus::<COLS_1024>(block, rounds)
+    } else {
+        t_plus::<COLS_512>(block, rounds)
+    }
+}
+
+/// The inverse of the T⊕l transformation.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds the forward transformation performed.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the block before the forward transformation.
+pub fn t_xor_inverse_l(block: &[u8], rounds: usize) -> Vec<u8> {
+    if block.len() == COLS_1024 * ROWS {
+        t_xor_inverse::<COLS_1024>(block, rounds)
+    } else {
+        t_xor_inverse::<COLS_512>(block, rounds)
+    }
+}
+
+/// The inverse of the T+l transformation.
+///
+/// # Arguments
+///
+/// * `block` - A byte slice representing the block to be transformed, 64 or 128 bytes long.
+/// * `rounds` - The number of rounds the forward transformation performed.
+///
+/// # Returns
+///
+/// * A `Vec<u8>` containing the block before the forward transformation.
+pub fn t_plus_inverse_l(block: &[u8], rounds: usize) -> Vec<u8> {
+    if block.len() == COLS_1024 * ROWS {
+        t_plus_inverse::<COLS_1024>(block, rounds)
+    } else {
+        t_plus_inverse::<COLS_512>(block, rounds)
     }
-    matrix_to_block(state)
 }
@@ -9,6 +9,17 @@ pub const MDS_MATRIX: [[u8; 8]; 8] = [
     [0x01, 0x05, 0x01, 0x08, 0x06, 0x07, 0x04, 0x01],
 ];
 
+pub const INVERSE_MDS_MATRIX: [[u8; 8]; 8] = [
+    [0xAD, 0x95, 0x76, 0xA8, 0x2F, 0x49, 0xD7, 0xCA],
+    [0xCA, 0xAD, 0x95, 0x76, 0xA8, 0x2F, 0x49, 0xD7],
+    [0xD7, 0xCA, 0xAD, 0x95, 0x76, 0xA8, 0x2F, 0x49],
+    [0x49, 0xD7, 0xCA, 0xAD, 0x95, 0x76, 0xA8, 0x2F],
+    [0x2F, 0x49, 0xD7, 0xCA, 0xAD, 0x95, 0x76, 0xA8],
+    [0xA8, 0x2F, 0x49, 0xD7, 0xCA, 0xAD, 0x95, 0x76],
+    [0x76, 0xA8, 0x2F, 0x49, 0xD7, 0xCA, 0xAD, 0x95],
+    [0x95, 0x76, 0xA8, 0x2F, 0x49, 0xD7, 0xCA, 0xAD],
+];
+
 pub const SBOXES: [[u8; 256]; 4] = [
     // The first subarray goes here
     [
@@ -95,3 +106,19 @@ pub const SBOXES: [[u8; 256]; 4] = [
         0x61,
     ],
 ];
+
+pub const INVERSE_SBOXES: [[u8; 256]; 4] = invert_sboxes();
+
+const fn invert_sboxes() -> [[u8; 256]; 4] {
+    let mut inverse = [[0u8; 256]; 4];
+    let mut s = 0;
+    while s < 4 {
+        let mut x = 0;
+        while x < 256 {
+            inverse[s][SBOXES[s][x] as usize] = x as u8;
+            x += 1;
+        }
+        s += 1;
+    }
+    inverse
+}
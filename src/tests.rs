@@ -1,8 +1,20 @@
 use super::*;
+#[cfg(feature = "bitsliced")]
+use crate::bitsliced;
+use crate::cavp::{self, Section, Vector};
+use crate::gost::{next_counter, Gost28147, InvalidLength, SBOX_DKE_1};
+use crate::permutation::{State1024, State512};
+use crate::pipeline::hash_reader_with;
 use crate::t_xor_plus::{
-    add_constant_plus, add_constant_xor, block_to_matrix, mix_columns, rotate_rows, s_box_layer,
+    add_constant_plus, add_constant_xor, block_to_matrix, inverse_add_constant_plus,
+    inverse_mix_columns, inverse_rotate_rows, inverse_s_box_layer, mix_columns, multiply_gf,
+    rotate_rows, s_box_layer, COLS_1024, COLS_512,
 };
-use crate::t_xor_plus::{t_plus_l, t_xor_l};
+use crate::t_xor_plus::{t_plus_inverse_l, t_plus_l, t_xor_inverse_l, t_xor_l};
+use crate::tables::{INVERSE_MDS_MATRIX, MDS_MATRIX};
+use crate::trace::{kupyna_hash_trace, Operation, Permutation, Stage, Step};
+#[cfg(feature = "table")]
+use crate::{multi_buffer, t_table};
 
 #[test]
 fn test_pad_message() {
@@ -69,7 +81,7 @@ fn test_add_constant_xor() {
         0x2D, 0x2E, 0x2F, 0x50, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x48, 0x39, 0x3A, 0x3B,
         0x3C, 0x3D, 0x3E, 0x3F,
     ];
-    let input_matrix = block_to_matrix(&input);
+    let input_matrix = block_to_matrix::<COLS_512>(&input);
     let result = add_constant_xor(input_matrix, 0);
     assert_eq!(result, block_to_matrix(&expected_output));
 }
@@ -90,11 +102,26 @@ fn test_add_constant_plus() {
         0x1E, 0x1F, 0x50, 0x23, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x48, 0x2B, 0x2A, 0x2B, 0x2C,
         0x2D, 0x2E, 0x2F, 0x40,
     ];
-    let input_matrix = block_to_matrix(&input);
+    let input_matrix = block_to_matrix::<COLS_512>(&input);
     let result = add_constant_plus(input_matrix, 0);
     assert_eq!(result, block_to_matrix(&expected_output));
 }
 
+#[test]
+fn test_add_constant_plus_carries_little_endian() {
+    // Row 0 is the low byte of each column, so carries run down the column and out of row 7.
+    // Reading the column in any other byte order gives different bytes on every target.
+    let mut input = [0xFF; 64];
+    input[..8].copy_from_slice(&[0x0D, 0x0F, 0x0F, 0x0F, 0x0F, 0x0F, 0x0F, 0x90]);
+    let mut expected_output = [0u8; 64];
+    expected_output[..8].copy_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    for (j, column) in expected_output.chunks_exact_mut(8).enumerate().skip(1) {
+        column.copy_from_slice(&[0xF2, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, (7 - j as u8) << 4]);
+    }
+    let result = add_constant_plus(block_to_matrix::<COLS_512>(&input), 0);
+    assert_eq!(result, block_to_matrix(&expected_output));
+}
+
 #[test]
 fn test_s_box_layer() {
     let input = [
@@ -111,7 +138,7 @@ fn test_s_box_layer() {
         0x67, 0xE1, 0x31, 0x4F, 0x64, 0xD8, 0xED, 0xB6, 0x22, 0x85, 0xE9, 0xE1, 0x02, 0xC8, 0xD1,
         0xD2, 0x73, 0x72, 0xB5,
     ];
-    let input_matrix = block_to_matrix(&input);
+    let input_matrix = block_to_matrix::<COLS_512>(&input);
     let result = s_box_layer(input_matrix);
     assert_eq!(result, block_to_matrix(&expected_output));
 }
@@ -132,7 +159,7 @@ fn test_rotate_rows() {
         0xED, 0x72, 0xE9, 0x4F, 0x36, 0xC2, 0x54, 0x75, 0xC0, 0x54, 0xB5, 0xE1, 0x64, 0xD4, 0x2D,
         0xF0, 0x13, 0x74, 0xEC,
     ];
-    let input_matrix = block_to_matrix(&input);
+    let input_matrix = block_to_matrix::<COLS_512>(&input);
     let result = rotate_rows(input_matrix);
     assert_eq!(result, block_to_matrix(&expected_output));
 }
@@ -153,7 +180,7 @@ fn test_mix_columns() {
         0x27, 0x3E, 0xC3, 0xAA, 0x3E, 0xC1, 0x41, 0xD6, 0xE3, 0x73, 0x58, 0x74, 0x79, 0x38, 0xEF,
         0x0A, 0x1C, 0x18, 0xEB,
     ];
-    let input_matrix = block_to_matrix(&input);
+    let input_matrix = block_to_matrix::<COLS_512>(&input);
     let result = mix_columns(input_matrix);
     assert_eq!(result, block_to_matrix(&expected_output));
 }
@@ -204,6 +231,95 @@ fn test_t_plus_l() {
     assert_eq!(result, expected_output);
 }
 
+#[cfg(feature = "table")]
+#[test]
+fn test_t_table_matches_reference_512() {
+    let input: Vec<u8> = (0..64).map(|i| (i * 37 + 11) as u8).collect();
+    for rounds in [1, 2, T_512] {
+        assert_eq!(t_table::t_xor_l(&input, rounds), t_xor_l(&input, rounds));
+        assert_eq!(t_table::t_plus_l(&input, rounds), t_plus_l(&input, rounds));
+    }
+}
+
+#[cfg(feature = "table")]
+#[test]
+fn test_t_table_matches_reference_1024() {
+    let input: Vec<u8> = (0..128).map(|i| (i * 73 + 5) as u8).collect();
+    for rounds in [1, 2, T_1024] {
+        assert_eq!(t_table::t_xor_l(&input, rounds), t_xor_l(&input, rounds));
+        assert_eq!(t_table::t_plus_l(&input, rounds), t_plus_l(&input, rounds));
+    }
+}
+
+#[cfg(feature = "bitsliced")]
+#[test]
+fn test_bitsliced_matches_reference_all_bytes() {
+    // Across 16 states every row of the 1024-bit state takes all 256 byte values
+    for t in 0..16 {
+        let input: Vec<u8> = (0..128).map(|k| (t * 16 + k / 8) as u8).collect();
+        assert_eq!(bitsliced::t_xor_l(&input, 1), t_xor_l(&input, 1));
+        assert_eq!(bitsliced::t_plus_l(&input, 1), t_plus_l(&input, 1));
+        assert_eq!(
+            bitsliced::t_xor_l(&input[..64], 1),
+            t_xor_l(&input[..64], 1)
+        );
+    }
+}
+
+#[cfg(feature = "bitsliced")]
+#[test]
+fn test_bitsliced_matches_reference_full_rounds() {
+    let input: Vec<u8> = (0..128).map(|i| (i * 73 + 5) as u8).collect();
+    assert_eq!(bitsliced::t_xor_l(&input, T_1024), t_xor_l(&input, T_1024));
+    assert_eq!(
+        bitsliced::t_plus_l(&input, T_1024),
+        t_plus_l(&input, T_1024)
+    );
+    assert_eq!(
+        bitsliced::t_xor_l(&input[..64], T_512),
+        t_xor_l(&input[..64], T_512)
+    );
+    assert_eq!(
+        bitsliced::t_plus_l(&input[..64], T_512),
+        t_plus_l(&input[..64], T_512)
+    );
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[test]
+fn test_simd_matches_reference() {
+    use crate::simd::{self, permute_with, Level};
+
+    let input: Vec<u8> = (0..128).map(|i| (i * 73 + 5) as u8).collect();
+    assert_eq!(simd::t_xor_l(&input, T_1024), t_xor_l(&input, T_1024));
+    assert_eq!(simd::t_plus_l(&input, T_1024), t_plus_l(&input, T_1024));
+    for level in [Level::Portable, Level::Ssse3, Level::Avx2] {
+        if !level.is_supported() {
+            continue;
+        }
+        for (block, rounds) in [
+            (&input[..64], 1),
+            (&input[..64], T_512),
+            (&input[..], T_1024),
+        ] {
+            assert_eq!(
+                permute_with(level, block, rounds, false),
+                t_xor_l(block, rounds)
+            );
+            assert_eq!(
+                permute_with(level, block, rounds, true),
+                t_plus_l(block, rounds)
+            );
+        }
+        // Every row takes all 256 byte values
+        for t in 0..16 {
+            let block: Vec<u8> = (0..128).map(|k| (t * 16 + k / 8) as u8).collect();
+            assert_eq!(permute_with(level, &block, 1, false), t_xor_l(&block, 1));
+            assert_eq!(permute_with(level, &block, 1, true), t_plus_l(&block, 1));
+        }
+    }
+}
+
 #[test]
 fn test_divide_into_blocks() {
     let message = b"hello";
@@ -252,14 +368,16 @@ fn test_kupyna_hash_empty_512() {
         vec![
             0x65, 0x6b, 0x2f, 0x4c, 0xd7, 0x14, 0x62, 0x38, 0x8b, 0x64, 0xa3, 0x70, 0x43, 0xea,
             0x55, 0xdb, 0xe4, 0x45, 0xd4, 0x52, 0xae, 0xcd, 0x46, 0xc3, 0x29, 0x83, 0x43, 0x31,
-            0x4e, 0xf0, 0x40, 0x19
+            0x4e, 0xf0, 0x40, 0x19, 0xbc, 0xfa, 0x3f, 0x04, 0x26, 0x5a, 0x98, 0x57, 0xf9, 0x1b,
+            0xe9, 0x1f, 0xce, 0x19, 0x70, 0x96, 0x18, 0x7c, 0xed, 0xa7, 0x8c, 0x9c, 0x1c, 0x02,
+            0x1c, 0x29, 0x4a, 0x06, 0x89, 0x19, 0x85, 0x38
         ],
     );
 }
 
 #[test]
 fn test_kupyna_hash_dog() {
-    let message = b"The quick brown fox jumps over the lazy dog.";
+    let message = b"The quick brown fox jumps over the lazy dog";
     let hash = kupyna_hash(message, 256);
     // Source: https://en.wikipedia.org/wiki/Kupyna
     assert_eq!(
@@ -286,3 +404,871 @@ fn test_kupyna_hash_dog_period() {
         ],
     );
 }
+
+#[test]
+fn test_dstu7564_known_answers() {
+    let sections = cavp::parse(include_str!("../kat/dstu7564_hash.rsp")).unwrap();
+    assert_eq!(
+        sections.iter().map(|s| s.vectors.len()).collect::<Vec<_>>(),
+        [8, 8, 8]
+    );
+    assert_eq!(cavp::check(&sections), []);
+
+    for section in &sections {
+        for vector in &section.vectors {
+            let Vector::Message { len, msg, md } = vector else {
+                unreachable!()
+            };
+            if len % 8 == 0 {
+                for &backend in Backend::ALL {
+                    assert_eq!(
+                        &kupyna_hash_with(backend, msg, section.n),
+                        md,
+                        "Kupyna-{}, {len} bits, {backend}",
+                        section.n
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cavp_round_trip() {
+    let data: Vec<u8> = (0..=255).collect();
+    let sections = vec![
+        Section {
+            n: 256,
+            vectors: vec![
+                cavp::message_vector(&[], 0, 256),
+                cavp::message_vector(&data, 13, 256),
+                cavp::message_vector(&data, 2048, 256),
+            ],
+        },
+        Section {
+            n: 256,
+            vectors: vec![cavp::monte_carlo_vector(&data[..32], 1, 256)],
+        },
+        Section {
+            n: 384,
+            vectors: vec![
+                cavp::kmac_vector(&data[..48], &[], 384).unwrap(),
+                cavp::kmac_vector(&data[..48], &data[..100], 384).unwrap(),
+            ],
+        },
+    ];
+    let text = cavp::write("Kupyna vectors\n\nGenerated by the kupyna crate", &sections);
+    assert!(text.starts_with("# Kupyna vectors\n#\n# Generated"));
+    assert!(text.contains("[L = 32]\n\nSeed = 000102"));
+    assert!(text.contains("Len = 0\nMsg = 00\nMD = cd5101d1"));
+
+    let parsed = cavp::parse(&text).unwrap();
+    assert_eq!(parsed, sections);
+    assert_eq!(cavp::check(&parsed), []);
+}
+
+#[test]
+fn test_cavp_reports_mismatches() {
+    let text =
+        "[L = 32]\n\nSeed = 00\n\nCOUNT = 0\nMD = 00\n\n[L = 64]\n\nLen = 8\nMsg = ff\nMD = 00\n";
+    let mismatches = cavp::check(&cavp::parse(text).unwrap());
+    assert_eq!(mismatches.len(), 2);
+    assert_eq!((mismatches[0].n, mismatches[0].checkpoint), (256, Some(0)));
+    assert_eq!((mismatches[1].n, mismatches[1].index), (512, 0));
+    assert_eq!(&mismatches[1].actual[..2], [0x87, 0x1b]);
+}
+
+#[test]
+fn test_cavp_parse_errors() {
+    let error = |text: &str| cavp::parse(text).unwrap_err();
+    assert_eq!(error("Len = 8").line, 1);
+    assert_eq!(error("[L = 32]\nLen = 16\nMsg = ff\nMD = 00").line, 4);
+    assert_eq!(error("[L = 32]\n\nMsg = 0g").line, 3);
+    assert_eq!(error("[L = 32]\nMD = 00").line, 2);
+    assert_eq!(error("[L = 32]\nTag = 00").message, "unknown field `Tag`");
+    assert_eq!(error("[L = 32]\n[L = 0]").line, 2);
+    assert_eq!(
+        error("[L = 65]").message,
+        "digest length of 65 bytes is not between 1 and 64"
+    );
+    assert_eq!(error("[L = x]").line, 1);
+    assert_eq!(error("[L = 32]\nLen = 8\nMsg = ff\nMac = 00").line, 4);
+    assert_eq!(
+        error("[L = 32]\nLen = 4\nKey = 00\nMsg = f0\nMac = 00").line,
+        5
+    );
+    assert_eq!(
+        error("[L = 32]\nLen = 8\nKey = 00\nMsg = ff\nMD = 00").line,
+        5
+    );
+    // A record cut off by a header is not finished by the next section's `MD`
+    let cut = error("[L = 32]\nLen = 8\nMsg = ff\n[L = 64]\nMD = 00");
+    assert_eq!(cut.line, 2);
+    assert_eq!(cut.message, "record ends without `MD` or `Mac`");
+    assert_eq!(error("[L = 32]\nKey = 00\n[Kupyna]\nLen = 0").line, 2);
+    // Nor is one at the end of the input dropped
+    let truncated = "[L = 32]\nLen = 8\nMsg = ff\nMD = 00\n\nLen = 8\nKey = 00\nMsg = ff\n";
+    assert_eq!(error(truncated).line, 6);
+    // Headers other than the digest length are ignored
+    assert_eq!(cavp::parse("[Kupyna]\n").unwrap(), []);
+}
+
+#[test]
+fn test_kupyna_hash_bits() {
+    // Bits past the length do not change the digest
+    assert_eq!(
+        kupyna_hash_bits(&[0b1010_1111], 4, 256),
+        kupyna_hash_bits(&[0b1010_0000], 4, 256)
+    );
+    assert_ne!(
+        kupyna_hash_bits(&[0b1010_0000], 4, 256),
+        kupyna_hash_bits(&[0b1010_0000], 5, 256)
+    );
+    assert_eq!(kupyna_hash_bits(b"abc", 24, 512), kupyna_hash(b"abc", 512));
+    let padded = pad_message_bits(&[0b1010_1111], 4, L512);
+    assert_eq!(padded.len(), 64);
+    assert_eq!(padded[0], 0b1010_1000);
+    assert_eq!(padded[52], 4);
+    // A message 97 bits short of a block boundary needs no zero bits
+    assert_eq!(pad_message_bits(&[0xFF; 52], 415, L512).len(), 64);
+    assert_eq!(pad_message_bits(&[0xFF; 52], 416, L512).len(), 128);
+    assert_eq!(pad_message_bits(&[0xFF; 116], 927, L1024).len(), 128);
+}
+
+#[test]
+#[should_panic(expected = "multiple of 8 from 8 to 512 bits, not 1032")]
+fn test_kupyna_hash_rejects_long_length() {
+    kupyna_hash(b"abc", 1032);
+}
+
+#[test]
+#[should_panic(expected = "multiple of 8 from 8 to 512 bits, not 252")]
+fn test_kupyna_hash_rejects_partial_byte_length() {
+    kupyna_hash(b"abc", 252);
+}
+
+#[test]
+fn test_public_entry_points_reject_bad_lengths() {
+    for n in [0, 4, 12, 520, 1024] {
+        let entry_points: [fn(usize); 8] = [
+            |n| drop(kupyna_hash_with(Backend::Reference, b"abc", n)),
+            |n| drop(kupyna_hash_bits(b"abc", 20, n)),
+            |n| drop(kupyna_hash_many(&[], n)),
+            |n| drop(KupynaHasher::new(n)),
+            |n| drop(kupyna_hash_reader(&b"abc"[..], n)),
+            |n| drop(kupyna_hash_trace(b"abc", n)),
+            |n| drop(hmac_kupyna(b"key", b"abc", n)),
+            |n| drop(hkdf_kupyna_expand(&[0; 32], b"", 0, n)),
+        ];
+        for (i, entry_point) in entry_points.into_iter().enumerate() {
+            assert!(
+                std::panic::catch_unwind(|| entry_point(n)).is_err(),
+                "entry point {i} accepted n = {n}"
+            );
+        }
+    }
+}
+
+const GOST_KEY: [u8; 32] = [
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF,
+    0xFF, 0xEE, 0xDD, 0xCC, 0xBB, 0xAA, 0x99, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00,
+];
+const GOST_IV: [u8; 8] = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0];
+
+#[test]
+fn test_gost_block_round_trip() {
+    let cipher = Gost28147::new(&GOST_KEY);
+    let block = [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+    let encrypted = cipher.encrypt_block(&block);
+    assert_ne!(encrypted, block);
+    assert_eq!(cipher.decrypt_block(&encrypted), block);
+}
+
+#[test]
+fn test_gost_sbox_changes_output() {
+    let block = [0u8; 8];
+    let mut sbox = SBOX_DKE_1;
+    sbox.swap(0, 1);
+    let dke = Gost28147::new(&GOST_KEY).encrypt_block(&block);
+    let other = Gost28147::with_sbox(&GOST_KEY, &sbox).encrypt_block(&block);
+    assert_ne!(dke, other);
+}
+
+#[test]
+fn test_gost_ecb() {
+    let cipher = Gost28147::new(&GOST_KEY);
+    let data: Vec<u8> = (0..32).collect();
+    let encrypted = cipher.encrypt_ecb(&data).unwrap();
+    assert_eq!(
+        &encrypted[8..16],
+        &cipher.encrypt_block(&[8, 9, 10, 11, 12, 13, 14, 15])
+    );
+    assert_eq!(cipher.decrypt_ecb(&encrypted).unwrap(), data);
+    assert_eq!(cipher.encrypt_ecb(&data[..31]), Err(InvalidLength));
+}
+
+#[test]
+fn test_gost_ctr() {
+    let cipher = Gost28147::new(&GOST_KEY);
+    let data: Vec<u8> = (0..21).collect();
+    let encrypted = cipher.ctr(&GOST_IV, &data);
+
+    let s = cipher.encrypt_block(&GOST_IV);
+    let n3 = u32::from_le_bytes([s[0], s[1], s[2], s[3]]).wrapping_add(0x01010101);
+    let n4 = u32::from_le_bytes([s[4], s[5], s[6], s[7]]);
+    let n4 = ((n4 as u64 + 0x01010104) % 0xFFFFFFFF) as u32;
+    let mut counter = [0u8; 8];
+    counter[..4].copy_from_slice(&n3.to_le_bytes());
+    counter[4..].copy_from_slice(&n4.to_le_bytes());
+    let gamma = cipher.encrypt_block(&counter);
+    assert_eq!(encrypted[0], data[0] ^ gamma[0]);
+    assert_eq!(encrypted[7], data[7] ^ gamma[7]);
+
+    assert_eq!(encrypted.len(), data.len());
+    assert_eq!(cipher.ctr(&GOST_IV, &encrypted), data);
+}
+
+#[test]
+fn test_gost_counter_wraps_modulo() {
+    assert_eq!(next_counter([0, 0]), [0x01010101, 0x01010104]);
+    // N4 is reduced modulo 2^32 - 1, N3 modulo 2^32
+    assert_eq!(
+        next_counter([0xFFFFFFFF, 0xFFFFFFF0]),
+        [0x01010100, 0x010100F5]
+    );
+}
+
+#[test]
+fn test_gost_cfb() {
+    let cipher = Gost28147::new(&GOST_KEY);
+    let data: Vec<u8> = (0..21).collect();
+    let encrypted = cipher.encrypt_cfb(&GOST_IV, &data);
+
+    let gamma = cipher.encrypt_block(&GOST_IV);
+    assert_eq!(encrypted[0], data[0] ^ gamma[0]);
+    let gamma = cipher.encrypt_block(&encrypted[..8].try_into().unwrap());
+    assert_eq!(encrypted[8], data[8] ^ gamma[0]);
+
+    assert_eq!(cipher.decrypt_cfb(&GOST_IV, &encrypted), data);
+}
+
+#[test]
+fn test_gost_mac() {
+    let cipher = Gost28147::new(&GOST_KEY);
+    let data: Vec<u8> = (0..20).collect();
+    let mac = cipher.mac(&data);
+
+    // Short final blocks are zero-padded
+    let mut padded = data.clone();
+    padded.resize(24, 0);
+    assert_eq!(cipher.mac(&padded), mac);
+
+    // A single block is extended with a zero block
+    let mut single = data[..8].to_vec();
+    single.resize(16, 0);
+    assert_eq!(cipher.mac(&data[..8]), cipher.mac(&single));
+
+    let mut tampered = data.clone();
+    tampered[3] ^= 1;
+    assert_ne!(cipher.mac(&tampered), mac);
+}
+
+// GOST R 34.12-2015 defines Magma as GOST 28147-89 with the tc26 "Z" S-boxes, written
+// big-endian. Its words are these crate's little-endian bytes reversed.
+const SBOX_TC26_Z: crate::gost::Sbox = [
+    [12, 4, 6, 2, 10, 5, 11, 9, 14, 8, 13, 7, 0, 3, 15, 1],
+    [6, 8, 2, 3, 9, 10, 5, 12, 1, 14, 4, 7, 11, 13, 0, 15],
+    [11, 3, 5, 8, 2, 15, 10, 13, 14, 1, 7, 4, 12, 9, 6, 0],
+    [12, 8, 2, 1, 13, 4, 15, 6, 7, 0, 10, 5, 3, 14, 9, 11],
+    [7, 15, 5, 10, 8, 1, 6, 13, 0, 9, 3, 14, 11, 4, 2, 12],
+    [5, 13, 15, 6, 9, 2, 12, 10, 11, 7, 8, 1, 4, 3, 14, 0],
+    [8, 14, 2, 5, 6, 9, 1, 12, 15, 4, 11, 0, 13, 10, 3, 7],
+    [1, 7, 14, 13, 0, 5, 8, 3, 4, 15, 10, 6, 9, 12, 11, 2],
+];
+const MAGMA_KEY: [u32; 8] = [
+    0xffeeddcc, 0xbbaa9988, 0x77665544, 0x33221100, 0xf0f1f2f3, 0xf4f5f6f7, 0xf8f9fafb, 0xfcfdfeff,
+];
+// The plaintext of the GOST R 34.13-2015 examples
+const MAGMA_PLAINTEXT: [u64; 4] = [
+    0x92def06b3c130a59,
+    0xdb54c704f8189d20,
+    0x4a98fb2e67a8024c,
+    0x8912409b17b57e41,
+];
+
+fn magma_cipher() -> Gost28147 {
+    let mut key = [0u8; 32];
+    for (chunk, word) in key.chunks_exact_mut(4).zip(MAGMA_KEY) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    Gost28147::with_sbox(&key, &SBOX_TC26_Z)
+}
+
+fn magma_bytes(blocks: &[u64]) -> Vec<u8> {
+    blocks
+        .iter()
+        .flat_map(|block| block.to_le_bytes())
+        .collect()
+}
+
+#[test]
+fn test_gost_ecb_magma_vectors() {
+    let cipher = magma_cipher();
+    // GOST R 34.12-2015, appendix A.2
+    assert_eq!(
+        cipher.encrypt_block(&0xfedcba9876543210u64.to_le_bytes()),
+        0x4ee901e5c2d8ca3du64.to_le_bytes()
+    );
+    // GOST R 34.13-2015, appendix A.2.1
+    let expected = magma_bytes(&[
+        0x2b073f0494f372a0,
+        0xde70e715d3556e48,
+        0x11d8d9e9eacfbc1e,
+        0x7c68260996c67efb,
+    ]);
+    let plaintext = magma_bytes(&MAGMA_PLAINTEXT);
+    assert_eq!(cipher.encrypt_ecb(&plaintext).unwrap(), expected);
+    assert_eq!(cipher.decrypt_ecb(&expected).unwrap(), plaintext);
+}
+
+#[test]
+fn test_gost_cfb_magma_vectors() {
+    // GOST R 34.13-2015, appendix A.2.5, feeds back through a two-block register, so its odd
+    // and even blocks are two GOST 28147-89 CFB chains, one for each half of the IV
+    let cipher = magma_cipher();
+    let expected = [
+        0xdb37e0e266903c83,
+        0x0d46644c1f9a089c,
+        0x24bdd2035315d38b,
+        0xbcc0321421075505,
+    ];
+    let p = MAGMA_PLAINTEXT;
+    for (iv, [first, second]) in [
+        (0x1234567890abcdefu64, [0, 2]),
+        (0x234567890abcdef1u64, [1, 3]),
+    ] {
+        let iv = iv.to_le_bytes();
+        let plaintext = magma_bytes(&[p[first], p[second]]);
+        let ciphertext = magma_bytes(&[expected[first], expected[second]]);
+        assert_eq!(cipher.encrypt_cfb(&iv, &plaintext), ciphertext);
+        assert_eq!(cipher.decrypt_cfb(&iv, &ciphertext), plaintext);
+    }
+}
+
+#[test]
+fn test_kupyna_hash_many_matches_single() {
+    let data: Vec<u8> = (0..700).map(|i| (i * 31 + 7) as u8).collect();
+    // Mixed lengths so lanes finish at different times, across the block boundaries
+    let messages: Vec<&[u8]> = [0, 1, 51, 52, 64, 200, 13, 700, 116, 5, 128]
+        .iter()
+        .map(|&len| &data[..len])
+        .collect();
+    for n in [256, 384, 512] {
+        let digests = kupyna_hash_many(&messages, n);
+        assert_eq!(digests.len(), messages.len());
+        for (message, digest) in messages.iter().zip(&digests) {
+            assert_eq!(digest, &kupyna_hash(message, n));
+        }
+        #[cfg(feature = "table")]
+        assert_eq!(multi_buffer::hash_many(&messages, n), digests);
+    }
+    assert!(kupyna_hash_many(&[], 256).is_empty());
+}
+
+/// A xorshift generator, so the cross-backend tests are reproducible.
+struct XorShift(u64);
+
+impl XorShift {
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0 as u8
+            })
+            .collect()
+    }
+}
+
+/// Blocks of `len` bytes that exercise every backend: all zeros, all ones, a single set bit in
+/// each byte position (cycling through the bit positions) and some random ones.
+fn edge_case_blocks(len: usize) -> Vec<Vec<u8>> {
+    let mut blocks = vec![vec![0x00; len], vec![0xFF; len]];
+    for byte in 0..len {
+        let mut block = vec![0x00; len];
+        block[byte] = 1 << (byte % 8);
+        blocks.push(block);
+    }
+    let mut rng = XorShift(0x9E3779B97F4A7C15);
+    blocks.extend((0..16).map(|_| rng.bytes(len)));
+    blocks
+}
+
+#[test]
+fn test_backends_match_reference_permutations() {
+    for (len, rounds) in [(64, [1, 2, T_512]), (128, [1, 2, T_1024])] {
+        for block in edge_case_blocks(len) {
+            for &rounds in &rounds {
+                let xor = Backend::Reference.t_xor_l(&block, rounds);
+                let plus = Backend::Reference.t_plus_l(&block, rounds);
+                for &backend in Backend::ALL {
+                    assert_eq!(backend.t_xor_l(&block, rounds), xor, "{backend} T⊕");
+                    assert_eq!(backend.t_plus_l(&block, rounds), plus, "{backend} T+");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_backends_match_reference_digests() {
+    let mut rng = XorShift(0x2545F4914F6CDD1D);
+    let data = rng.bytes(300);
+    // Lengths either side of the padding and block boundaries of both state sizes
+    for len in [
+        0, 1, 51, 52, 63, 64, 65, 115, 116, 127, 128, 129, 255, 256, 300,
+    ] {
+        for n in [8, 256, 264, 384, 512] {
+            let expected = kupyna_hash_with(Backend::Reference, &data[..len], n);
+            for &backend in Backend::ALL {
+                assert_eq!(
+                    kupyna_hash_with(backend, &data[..len], n),
+                    expected,
+                    "{backend}, {len} bytes, n = {n}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_backend_names() {
+    for &backend in Backend::ALL {
+        assert_eq!(backend.to_string().parse(), Ok(backend));
+    }
+    assert_eq!(
+        "nonsense".parse::<Backend>(),
+        Err(UnknownBackend("nonsense".to_string()))
+    );
+    assert!(Backend::ALL.contains(&Backend::default()));
+    if cfg!(feature = "constant-time") {
+        assert!(Backend::default().is_constant_time());
+    }
+}
+
+#[test]
+fn test_state_rounds_match_permutations() {
+    let input: Vec<u8> = (0..128).map(|i| (i * 73 + 5) as u8).collect();
+    let mut xor = State1024::new(input.clone().try_into().unwrap());
+    let mut plus = xor;
+    for rounds in 1..=State1024::ROUNDS {
+        xor.xor_round(rounds - 1);
+        plus.plus_round(rounds - 1);
+        assert_eq!(xor.to_bytes(), t_xor_l(&input, rounds));
+        assert_eq!(plus.to_bytes(), t_plus_l(&input, rounds));
+
+        let mut whole = State1024::new(input.clone().try_into().unwrap());
+        whole.t_xor(rounds);
+        assert_eq!(whole, xor);
+        whole = State1024::new(input.clone().try_into().unwrap());
+        whole.t_plus(rounds);
+        assert_eq!(whole, plus);
+    }
+
+    let mut state = State512::new(input[..64].try_into().unwrap());
+    state.t_plus(State512::ROUNDS);
+    assert_eq!(state.to_bytes(), t_plus_l(&input[..64], T_512));
+    assert_eq!(state.columns()[3].to_vec(), state.to_bytes()[24..32]);
+}
+
+#[test]
+fn test_state_round_constants() {
+    assert_eq!(State512::ROUNDS, 10);
+    assert_eq!(State1024::ROUNDS, 14);
+    assert_eq!(State512::xor_constant(0, 0), 0x00);
+    assert_eq!(State512::xor_constant(3, 7), 0x73);
+    assert_eq!(State512::plus_constant(0, 7), 0x00F0F0F0F0F0F0F3);
+    assert_eq!(State512::plus_constant(0, 0), 0x70F0F0F0F0F0F0F3);
+    assert_eq!(State1024::plus_constant(2, 0), 0xF2F0F0F0F0F0F0F3);
+}
+
+#[test]
+#[should_panic(expected = "rounds must be between 1 and 10")]
+fn test_state_rejects_too_many_rounds() {
+    State512::new([0; 64]).t_xor(11);
+}
+
+#[test]
+fn test_inverse_mds_matrix() {
+    let product: [[u8; 8]; 8] = std::array::from_fn(|row| {
+        std::array::from_fn(|col| {
+            (0..8).fold(0, |sum, k| {
+                sum ^ multiply_gf(MDS_MATRIX[row][k], INVERSE_MDS_MATRIX[k][col])
+            })
+        })
+    });
+    let identity: [[u8; 8]; 8] =
+        std::array::from_fn(|row| std::array::from_fn(|col| (row == col) as u8));
+    assert_eq!(product, identity);
+}
+
+#[test]
+fn test_inverse_steps_round_trip() {
+    for block in edge_case_blocks(128) {
+        let state = block_to_matrix::<COLS_1024>(&block);
+        assert_eq!(inverse_s_box_layer(s_box_layer(state)), state);
+        assert_eq!(inverse_rotate_rows(rotate_rows(state)), state);
+        assert_eq!(inverse_mix_columns(mix_columns(state)), state);
+        assert_eq!(add_constant_xor(add_constant_xor(state, 5), 5), state);
+        assert_eq!(
+            inverse_add_constant_plus(add_constant_plus(state, 5), 5),
+            state
+        );
+
+        let state = block_to_matrix::<COLS_512>(&block[..64]);
+        assert_eq!(inverse_rotate_rows(rotate_rows(state)), state);
+    }
+}
+
+#[test]
+fn test_inverse_permutations_round_trip() {
+    let mut rng = XorShift(0xD1B54A32D192ED03);
+    for (len, max_rounds) in [(64, T_512), (128, T_1024)] {
+        for rounds in 1..=max_rounds {
+            let block = rng.bytes(len);
+            assert_eq!(t_xor_inverse_l(&t_xor_l(&block, rounds), rounds), block);
+            assert_eq!(t_plus_inverse_l(&t_plus_l(&block, rounds), rounds), block);
+        }
+    }
+
+    let mut state = State512::new(rng.bytes(64).try_into().unwrap());
+    let original = state;
+    state.t_plus(State512::ROUNDS);
+    state.t_plus_inverse(State512::ROUNDS);
+    assert_eq!(state, original);
+    state.xor_round(3);
+    state.inverse_xor_round(3);
+    state.plus_round(9);
+    state.inverse_plus_round(9);
+    assert_eq!(state, original);
+    state.t_xor(4);
+    state.t_xor_inverse(4);
+    assert_eq!(state, original);
+}
+
+#[test]
+fn test_trace_records_every_step() {
+    let message = [0x5Au8; 100];
+    for (n, columns, rounds) in [(256, COLS_512, T_512), (512, COLS_1024, T_1024)] {
+        let trace = kupyna_hash_trace(&message, n);
+        assert_eq!(trace.digest, kupyna_hash(&message, n));
+        assert_eq!(trace.columns, columns);
+
+        // Each permutation has its input and four states per round
+        let blocks = pad_message(&message, columns * 64).len() / (columns * 8);
+        let per_permutation = 1 + 4 * rounds;
+        assert_eq!(
+            trace.entries.len(),
+            blocks * (2 * per_permutation + 1) + per_permutation + 1
+        );
+
+        let last = &trace.entries[trace.entries.len() - 2];
+        assert_eq!(last.stage, Stage::Output);
+        assert_eq!(
+            last.step,
+            Step::Round {
+                permutation: Permutation::Xor,
+                round: rounds - 1,
+                operation: Operation::MixColumns,
+            }
+        );
+        let first_input = &trace.entries[0].state;
+        assert_eq!(last.state.len(), columns * 8);
+        assert_eq!(
+            trace.entries[per_permutation - 1].state,
+            t_xor_l(first_input, rounds)
+        );
+    }
+}
+
+#[test]
+fn test_trace_display_layout() {
+    let trace = kupyna_hash_trace(b"", 256);
+    let text = trace.to_string();
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some("Block 0, T⊕ input:"));
+    // The IV XORed with the padding: 0x40 ^ 0x80 in row 0 of column 0
+    assert_eq!(lines.next(), Some("C0 00 00 00 00 00 00 00"));
+    assert!(text.contains("Block 0, T+ round 9, mix_columns:"));
+    assert!(text.contains("Output, T⊕ round 0, add_constant_xor:"));
+    assert!(text.ends_with(&format!(
+        "Digest: {}\n",
+        trace
+            .digest
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<String>()
+    )));
+}
+
+#[test]
+fn test_hasher_matches_one_shot() {
+    let mut rng = XorShift(0x94D049BB133111EB);
+    let data = rng.bytes(600);
+    // Chunk sizes that straddle the 64- and 128-byte blocks in different ways
+    let schedules: [&[usize]; 5] = [&[600], &[1], &[0, 63, 2], &[64, 128, 5], &[127, 129, 1, 0]];
+    for n in [8, 256, 264, 512] {
+        for len in [0, 1, 63, 64, 65, 128, 300, 600] {
+            let message = &data[..len];
+            let expected = kupyna_hash(message, n);
+            for schedule in schedules {
+                let mut hasher = KupynaHasher::new(n);
+                let mut rest = message;
+                for &size in schedule.iter().cycle().take(rest.len() + schedule.len()) {
+                    let (chunk, tail) = rest.split_at(size.min(rest.len()));
+                    hasher.update(chunk);
+                    rest = tail;
+                }
+                hasher.update(rest);
+                assert_eq!(hasher.finalize(), expected, "n = {n}, {len} bytes");
+            }
+        }
+    }
+}
+
+/// A reader that returns at most `chunk` bytes a time, then fails after `fail_after` bytes.
+struct Trickle<'a> {
+    data: &'a [u8],
+    chunk: usize,
+    fail_after: Option<usize>,
+    read: usize,
+}
+
+impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.fail_after.is_some_and(|limit| self.read >= limit) {
+            return Err(std::io::Error::other("disk on fire"));
+        }
+        let len = self.chunk.min(buf.len()).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        self.read += len;
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_pipelined_hash_matches_one_shot() {
+    let data = XorShift(0xD6E8FEB86659FD93).bytes(1000);
+    // Buffers smaller than, equal to and larger than a block, and data ending on and off them
+    for buffer_len in [1, 64, 100, 256] {
+        for len in [0, 1, 63, 64, 100, 256, 257, 1000] {
+            for chunk in [1, 7, 1000] {
+                let reader = Trickle {
+                    data: &data[..len],
+                    chunk,
+                    fail_after: None,
+                    read: 0,
+                };
+                assert_eq!(
+                    hash_reader_with(reader, 256, buffer_len).unwrap(),
+                    kupyna_hash(&data[..len], 256),
+                    "buffer {buffer_len}, {len} bytes in chunks of {chunk}"
+                );
+            }
+        }
+    }
+    assert_eq!(
+        kupyna_hash_reader(&data[..], 512).unwrap(),
+        kupyna_hash(&data, 512)
+    );
+}
+
+#[test]
+fn test_pipelined_hash_errors() {
+    let data = [0; 1000];
+    for fail_after in [0, 10, 500] {
+        let reader = Trickle {
+            data: &data,
+            chunk: 10,
+            fail_after: Some(fail_after),
+            read: 0,
+        };
+        let error = hash_reader_with(reader, 256, 64).unwrap_err();
+        assert_eq!(error.to_string(), "disk on fire");
+    }
+
+    let missing = std::env::temp_dir().join("kupyna-missing-file");
+    assert_eq!(
+        kupyna_hash_file(missing, 256).unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn test_hash_file() {
+    let data = XorShift(0x2545F4914F6CDD1D).bytes(3000);
+    let path = std::env::temp_dir().join(format!("kupyna-hash-file-{}", std::process::id()));
+    std::fs::write(&path, &data).unwrap();
+    let digest = kupyna_hash_file(&path, 384);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(digest.unwrap(), kupyna_hash(&data, 384));
+}
+
+#[test]
+fn test_hmac() {
+    let message = XorShift(0x9E3779B97F4A7C15).bytes(300);
+    // Keys shorter than, as long as and longer than each block size
+    for n in [256, 512] {
+        let block_len = state_params(n).0 / 8;
+        for key_len in [0, 20, 64, 128, 200] {
+            let key = XorShift(key_len as u64 + 1).bytes(key_len);
+            let mut block = if key_len > block_len {
+                kupyna_hash(&key, n)
+            } else {
+                key.clone()
+            };
+            block.resize(block_len, 0);
+            let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<_>>();
+            let inner = kupyna_hash(&[pad(0x36), message.clone()].concat(), n);
+            let expected = kupyna_hash(&[pad(0x5c), inner].concat(), n);
+            assert_eq!(hmac_kupyna(&key, &message, n), expected, "{n}, {key_len}");
+
+            let mut mac = HmacKupyna::new(&key, n);
+            for chunk in message.chunks(7) {
+                mac.update(chunk);
+            }
+            assert_eq!(mac.finalize(), expected);
+        }
+    }
+    // As in any HMAC, short keys are padded with zeros
+    assert_eq!(
+        hmac_kupyna(b"key", b"", 256),
+        hmac_kupyna(b"key\0", b"", 256)
+    );
+    assert_ne!(hmac_kupyna(b"key", b"", 256), hmac_kupyna(b"kez", b"", 256));
+}
+
+#[test]
+fn test_kmac() {
+    let message = XorShift(0xC2B2AE3D27D4EB4F).bytes(300);
+    for n in [256, 384, 512] {
+        let key = XorShift(n as u64).bytes(n / 8);
+        let inverted: Vec<u8> = key.iter().map(|b| !b).collect();
+        let padded_key = pad_message(&key, state_params(n).0);
+        assert_eq!(padded_key.len() * 8, state_params(n).0);
+        let expected = kupyna_hash(&[padded_key, message.clone(), inverted].concat(), n);
+        assert_eq!(kmac_kupyna(&key, &message, n).unwrap(), expected);
+
+        let mut mac = KmacKupyna::new(&key, n).unwrap();
+        for chunk in message.chunks(13) {
+            mac.update(chunk);
+        }
+        assert_eq!(mac.finalize(), expected);
+    }
+
+    let error = KmacKupyna::new(&[0; 31], 256).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "KMAC-256 needs a 32-byte key, not 31 bytes"
+    );
+    let error = kmac_kupyna(&[0; 16], b"", 128).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "KMAC codes are 256, 384 or 512 bits, not 128"
+    );
+}
+
+#[test]
+fn test_verify_mac() {
+    let code = hmac_kupyna(b"key", b"message", 256);
+    assert!(verify_mac(&code, &code.clone()));
+    for i in [0, 15, 31] {
+        let mut forged = code.clone();
+        forged[i] ^= 1;
+        assert!(!verify_mac(&code, &forged));
+    }
+    assert!(!verify_mac(&code, &code[..31]));
+    assert!(verify_mac(&[], &[]));
+}
+
+#[test]
+fn test_hkdf() {
+    let ikm = XorShift(0x853C49E6748FEA9B).bytes(40);
+    let (salt, info) = (b"salt".as_slice(), b"service".as_slice());
+    for n in [256, 512] {
+        let prk = hmac_kupyna(salt, &ikm, n);
+        assert_eq!(hkdf_kupyna_extract(salt, &ikm, n), prk);
+        let mut expected = Vec::new();
+        let mut block = Vec::new();
+        for counter in 1..=3u8 {
+            block = hmac_kupyna(&prk, &[&block[..], info, &[counter]].concat(), n);
+            expected.extend_from_slice(&block);
+        }
+        // Shorter than, as long as, and longer than one hash code
+        for len in [0, 1, n / 8, n / 8 + 1, 3 * n / 8] {
+            assert_eq!(
+                hkdf_kupyna(&ikm, salt, info, len, n).unwrap(),
+                expected[..len],
+                "{n}, {len}"
+            );
+        }
+        let max = 255 * n / 8;
+        assert_eq!(hkdf_kupyna(&ikm, salt, info, max, n).unwrap().len(), max);
+        assert_eq!(
+            hkdf_kupyna(&ikm, salt, info, max + 1, n),
+            Err(InvalidOutputLength { len: max + 1, max })
+        );
+    }
+    // An empty salt is the RFC's default of zeros
+    assert_eq!(
+        hkdf_kupyna_extract(b"", &ikm, 256),
+        hkdf_kupyna_extract(&[0; 32], &ikm, 256)
+    );
+    assert_eq!(
+        InvalidOutputLength {
+            len: 9000,
+            max: 8160
+        }
+        .to_string(),
+        "HKDF output is at most 8160 bytes, not 9000"
+    );
+}
+
+#[test]
+fn test_pbkdf2() {
+    let (password, salt) = (b"correct horse".as_slice(), b"NaCl".as_slice());
+    for n in [256, 384] {
+        let f = |iterations: u32, index: u32| {
+            let mut u = hmac_kupyna(password, &[salt, &index.to_be_bytes()].concat(), n);
+            let mut block = u.clone();
+            for _ in 1..iterations {
+                u = hmac_kupyna(password, &u, n);
+                block = xor_bytes(&block, &u);
+            }
+            block
+        };
+        for iterations in [1, 2, 5] {
+            let expected = [f(iterations, 1), f(iterations, 2)].concat();
+            for len in [1, n / 8, n / 8 + 5, 2 * n / 8] {
+                assert_eq!(
+                    pbkdf2_kupyna(password, salt, iterations, len, n),
+                    expected[..len],
+                    "{n}, {iterations}, {len}"
+                );
+            }
+        }
+    }
+    assert!(pbkdf2_kupyna(password, salt, 1, 0, 256).is_empty());
+    assert_ne!(
+        pbkdf2_kupyna(password, salt, 2, 32, 256),
+        pbkdf2_kupyna(password, salt, 3, 32, 256)
+    );
+}
+
+#[test]
+#[should_panic(expected = "at least one iteration")]
+fn test_pbkdf2_zero_iterations() {
+    pbkdf2_kupyna(b"password", b"salt", 0, 32, 256);
+}
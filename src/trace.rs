@@ -0,0 +1,252 @@
+//! Hashing with a record of every intermediate state.
+//!
+//! `kupyna_hash_trace` runs the reference steps and keeps the state after each of them, for
+//! every block and round, so a disagreeing implementation can be diffed against it. A `Trace`
+//! prints each state as the standard's annex does: eight rows, each listing its byte in every
+//! column.
+
+use std::fmt;
+
+use crate::t_xor_plus::{
+    add_constant_plus, add_constant_xor, block_to_matrix, matrix_to_block, mix_columns,
+    rotate_rows, s_box_layer, Matrix, COLS_1024, COLS_512, ROWS,
+};
+use crate::{divide_into_blocks, initial_state, pad_message, r_l_n, state_params, xor_bytes, L512};
+
+/// Which part of the hash computation a state belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// The compression of the padded message block with this index.
+    Block(usize),
+    /// The output transformation.
+    Output,
+}
+
+/// One of the two permutations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permutation {
+    Xor,
+    Plus,
+}
+
+/// A step within a round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// `add_constant_xor` in T⊕l, `add_constant_plus` in T+l.
+    AddConstant,
+    SBoxLayer,
+    RotateRows,
+    MixColumns,
+}
+
+/// What produced a traced state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// The state a permutation starts from.
+    Input { permutation: Permutation },
+    /// The state after one step of a round, counting rounds from 0.
+    Round {
+        permutation: Permutation,
+        round: usize,
+        operation: Operation,
+    },
+    /// The chaining value after a block, or the output transformation's result before
+    /// truncation.
+    Result,
+}
+
+/// A state recorded during hashing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub stage: Stage,
+    pub step: Step,
+    /// The state's bytes, column by column.
+    pub state: Vec<u8>,
+}
+
+/// Every intermediate state of one hash computation, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    /// The number of columns in the state: 8 or 16.
+    pub columns: usize,
+    pub entries: Vec<TraceEntry>,
+    pub digest: Vec<u8>,
+}
+
+/// Computes the Kupyna hash of the input message, recording every intermediate state.
+///
+/// # Arguments
+///
+/// * `message` - A byte slice representing the message to be hashed.
+/// * `n` - The length of the hash code in bits.
+///
+/// # Returns
+///
+/// * A `Trace` with the intermediate states and the hash code.
+///
+/// # Panics
+///
+/// If `n` is not a multiple of 8 from 8 to 512.
+pub fn kupyna_hash_trace(message: &[u8], n: usize) -> Trace {
+    if state_params(n).0 == L512 {
+        trace::<COLS_512>(message, n)
+    } else {
+        trace::<COLS_1024>(message, n)
+    }
+}
+
+fn trace<const C: usize>(message: &[u8], n: usize) -> Trace {
+    let (l, t) = state_params(n);
+    let mut entries = Vec::new();
+
+    let padded_message = pad_message(message, l);
+    let mut h = initial_state(l);
+
+    for (index, block) in divide_into_blocks(&padded_message, l)
+        .into_iter()
+        .enumerate()
+    {
+        let stage = Stage::Block(index);
+        let t_xor = permute::<C>(
+            &mut entries,
+            stage,
+            Permutation::Xor,
+            &xor_bytes(&h, block),
+            t,
+        );
+        let t_plus = permute::<C>(&mut entries, stage, Permutation::Plus, block, t);
+        h = xor_bytes(&xor_bytes(&t_xor, &t_plus), &h);
+        entries.push(TraceEntry {
+            stage,
+            step: Step::Result,
+            state: h.clone(),
+        });
+    }
+
+    let t_xor = permute::<C>(&mut entries, Stage::Output, Permutation::Xor, &h, t);
+    let result = xor_bytes(&t_xor, &h);
+    entries.push(TraceEntry {
+        stage: Stage::Output,
+        step: Step::Result,
+        state: result.clone(),
+    });
+
+    Trace {
+        columns: C,
+        entries,
+        digest: r_l_n(&result, n),
+    }
+}
+
+/// A round step after the constant addition.
+type Round<const C: usize> = fn(Matrix<C>) -> Matrix<C>;
+
+/// Runs one permutation with the reference steps, recording the state after each of them.
+fn permute<const C: usize>(
+    entries: &mut Vec<TraceEntry>,
+    stage: Stage,
+    permutation: Permutation,
+    block: &[u8],
+    rounds: usize,
+) -> Vec<u8> {
+    let mut record = |step: Step, state: &Matrix<C>| {
+        entries.push(TraceEntry {
+            stage,
+            step,
+            state: matrix_to_block(*state),
+        });
+    };
+
+    let steps: [(Operation, Round<C>); 3] = [
+        (Operation::SBoxLayer, s_box_layer),
+        (Operation::RotateRows, rotate_rows),
+        (Operation::MixColumns, mix_columns),
+    ];
+
+    let mut state = block_to_matrix::<C>(block);
+    record(Step::Input { permutation }, &state);
+    for round in 0..rounds {
+        state = match permutation {
+            Permutation::Xor => add_constant_xor(state, round),
+            Permutation::Plus => add_constant_plus(state, round),
+        };
+        record(
+            Step::Round {
+                permutation,
+                round,
+                operation: Operation::AddConstant,
+            },
+            &state,
+        );
+        for (operation, apply) in steps {
+            state = apply(state);
+            record(
+                Step::Round {
+                    permutation,
+                    round,
+                    operation,
+                },
+                &state,
+            );
+        }
+    }
+    matrix_to_block(state)
+}
+
+impl fmt::Display for Permutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Permutation::Xor => "T⊕",
+            Permutation::Plus => "T+",
+        })
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stage {
+            Stage::Block(index) => write!(f, "Block {}, ", index)?,
+            Stage::Output => write!(f, "Output, ")?,
+        }
+        match self.step {
+            Step::Input { permutation } => writeln!(f, "{} input:", permutation)?,
+            Step::Round {
+                permutation,
+                round,
+                operation,
+            } => {
+                let name = match (operation, permutation) {
+                    (Operation::AddConstant, Permutation::Xor) => "add_constant_xor",
+                    (Operation::AddConstant, Permutation::Plus) => "add_constant_plus",
+                    (Operation::SBoxLayer, _) => "s_box_layer",
+                    (Operation::RotateRows, _) => "rotate_rows",
+                    (Operation::MixColumns, _) => "mix_columns",
+                };
+                writeln!(f, "{} round {}, {}:", permutation, round, name)?;
+            }
+            Step::Result if self.stage == Stage::Output => writeln!(f, "result:")?,
+            Step::Result => writeln!(f, "chaining value:")?,
+        }
+
+        // Row i lists byte i of every column
+        for row in 0..ROWS {
+            let bytes: Vec<String> = self
+                .state
+                .chunks(ROWS)
+                .map(|column| format!("{:02X}", column[row]))
+                .collect();
+            writeln!(f, "{}", bytes.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        let digest: String = self.digest.iter().map(|b| format!("{:02X}", b)).collect();
+        writeln!(f, "Digest: {}", digest)
+    }
+}